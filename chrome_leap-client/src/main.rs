@@ -1,27 +1,126 @@
 mod utils;
 
 use std::env;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::time::Duration;
 
-use serde::{Deserialize, Serialize};
 use tokio::net::{TcpStream};
-use tokio::io::{AsyncBufReadExt, AsyncReadExt, BufReader};
-use utils::chrome::open_chrome;
+use tokio::io::BufReader;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{sleep, sleep_until, Instant};
 use dotenv::dotenv;
+use chrome_leap_core::auth::{Session, KEYS_DIR};
+use chrome_leap_core::browser::{self, BrowserLauncher};
+use chrome_leap_core::delivery::{self, Due, Pending, Replies, Retry};
+use chrome_leap_core::frame::{write_frame, FrameReader};
+use chrome_leap_core::local_ws::{self, Extensions, LocalEvent, WsGuard};
+use chrome_leap_core::registry::Registry;
+use chrome_leap_core::os_check::{edge_check, EdgeConfig};
+use chrome_leap_core::transport::{BoxLink, Transport};
+use chrome_leap_core::url_policy::{Pattern, UrlPolicy};
+use chrome_leap_proto::handshake::{Hello, CAP_OPEN_TABS};
+use chrome_leap_proto::{ClientMsg, GlobalMsg, time_now};
 
-#[derive(Deserialize, Debug , Serialize)]
-#[serde(tag = "action")]
-enum GlobalMsg {
-    #[serde(rename = "tabs")]
-    Tabs { tabs: Vec<String> , time : String}
-}
+use crate::utils::backoff::Backoff;
+
+//local computer ---- ping ----> server every HEARTBEAT , nothing back for DEAD_AFTER = the link is half open
+const HEARTBEAT: Duration = Duration::from_secs(10);
+const DEAD_AFTER: Duration = Duration::from_secs(30);
+
+//a link that stayed up this long starts the next reconnect from the short wait again
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+//throws held while the link is down , the oldest goes when more come
+const MAX_OFFLINE: usize = 20;
 
 fn time_now_ms() -> u128 {
     use std::time::{SystemTime, UNIX_EPOCH};
     SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
-        .as_nanos() as u128 // timestamp ms
+        .as_nanos() // timestamp ms
+}
+
+async fn connect(addr : &str , fingerprint : Option<&str>) -> anyhow::Result<BoxLink> {
+    match fingerprint {
+        Some(fingerprint) => Ok(Box::new(utils::quic::connect(addr , fingerprint).await?)),
+        None => {
+            let stream = TcpStream::connect(addr).await?;
+
+            //the os notices a dead peer on its own too , even when we're not writing
+            let keepalive = socket2::TcpKeepalive::new()
+                .with_time(Duration::from_secs(15))
+                .with_interval(Duration::from_secs(5));
+            if let Err(e) = socket2::SockRef::from(&stream).set_tcp_keepalive(&keepalive) {
+                println!("[LINK] tcp keepalive not available : {}" , e);
+            }
+            Ok(Box::new(stream))
+        }
+    }
+}
+
+//where the server is , asked again before every reconnect so a server that moved is found
+async fn target(env_transport : Option<Transport>) -> anyhow::Result<(String , Transport , Option<String>)> {
+    //PORT ( server ip ) skips discovery , otherwise find the server on the lan
+    match env::var("PORT") {
+        Ok(ip) => {
+            //TRANSPORT=quic needs SERVER_FINGERPRINT , the server prints it on start
+            let transport = env_transport.unwrap_or_default();
+            let fingerprint = match transport {
+                Transport::Quic => Some(env::var("SERVER_FINGERPRINT").map_err(|_| anyhow::anyhow!("TRANSPORT=quic needs SERVER_FINGERPRINT to pin the server certificate"))?),
+                Transport::Tcp => None,
+            };
+            Ok((format!("{}:24811" , ip) , transport , fingerprint))
+        }
+        Err(_) => {
+            let peers = utils::discover::peers().await?;
+            utils::discover::print(&peers);
+            let peer = utils::discover::pick(peers , env::var("SERVER").ok().as_deref())?;
+
+            //SERVER_FINGERPRINT wins , otherwise the advertised one is pinned on first use and has to stay the same
+            let transport = env_transport.unwrap_or(peer.transport);
+            let fingerprint = match transport {
+                Transport::Quic => Some(match env::var("SERVER_FINGERPRINT") {
+                    Ok(fingerprint) => fingerprint,
+                    Err(_) => utils::quic::trusted_fingerprint(Path::new(KEYS_DIR) , &peer.hostname , peer.fingerprint.as_deref())?,
+                }),
+                Transport::Tcp => None,
+            };
+            let addr = peer.addr().ok_or_else(|| anyhow::anyhow!("'{}' did not advertise an address" , peer.hostname))?;
+            println!("[MDNS] using '{}' @ {}" , peer.hostname , addr);
+            Ok((addr.to_string() , transport , fingerprint))
+        }
+    }
+}
+
+//find the server , connect and say hello , the link is ready for auth / pair after this
+async fn open_link(env_transport : Option<Transport>) -> anyhow::Result<(BufReader<BoxLink> , Hello)> {
+    let (addr , transport , fingerprint) = target(env_transport).await?;
+    println!("[LINK] connecting to {} ({:?})" , addr , transport);
+    let stream = connect(&addr , fingerprint.as_deref()).await.map_err(|e| anyhow::anyhow!("Fail to connect with err : {}" , e))?;
+    let mut reader = BufReader::new(stream);
+
+    //local computer ---- [hello] ----> server
+    let server = utils::handshake::connect(&mut reader).await?;
+    println!("[TCP][HANDSHAKE] connected to '{}' (protocol v{})" , server.hostname , server.version);
+    Ok((reader , server))
+}
+
+//"a, b ,c" ----> ["a", "b", "c"] , unset = empty
+fn list_env(key: &str) -> Vec<String> {
+    env::var(key).unwrap_or_default().split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()
+}
+
+//what outlives a single connection
+struct Local {
+    extensions : Extensions,
+    browser : Option<Box<dyn BrowserLauncher>>,
+    policy : UrlPolicy,
+    tabs_rx : mpsc::Receiver<ClientMsg>,
+    //tabs we sent and wait an answer for , answers we gave in case the server retries
+    pending : Pending,
+    replies : Replies,
 }
 
 #[tokio::main]
@@ -29,42 +128,284 @@ async fn main() -> anyhow::Result<()> {
 
     dotenv().ok();
 
-    let addr = format!("{}:24811" , env::var("PORT").unwrap());
+    //`chrome_leap-client discover` only lists the servers on the lan
+    if env::args().nth(1).as_deref() == Some("discover") {
+        let peers = utils::discover::peers().await?;
+        if peers.is_empty() {
+            println!("[MDNS] no server found on the lan");
+        }
+        utils::discover::print(&peers);
+        return Ok(());
+    }
 
-    let mut delay= 1;
-    let mut stream = loop {
-        match TcpStream::connect(&addr).await {
-            Ok(s) => {
-                println!("connected");
-                break s;
-            }
-            Err(e) => {
-                println!("Fail to connect with err : {}" , e);
-                tokio::time::sleep(Duration::from_secs(delay)).await;
+    let env_transport = match env::var("TRANSPORT") {
+        Ok(t) => Some(Transport::parse(&t).ok_or_else(|| anyhow::anyhow!("TRANSPORT must be tcp or quic , got '{}'" , t))?),
+        Err(_) => None,
+    };
 
-                delay = (delay * 2).min(30); //cap at 30 sec 
+    let mut backoff = Backoff::new(Duration::from_secs(1) , Duration::from_secs(30));
+
+    //`chrome_leap-client pair` only stores the secret and exits
+    if env::args().nth(1).as_deref() == Some("pair") {
+        let (mut reader , server) = loop {
+            match open_link(env_transport).await {
+                Ok(link) => break link,
+                Err(e) => {
+                    println!("[LINK] {}" , e);
+                    sleep(backoff.next()).await;
+                }
             }
+        };
+        return utils::handshake::pair(&mut reader , &server).await;
+    }
+
+    //BROWSER=firefox ( or chrome , brave ... ) , BROWSER_COMMAND="my-browser {url}" , neither = first one installed
+    let browser = match browser::select(env::var("BROWSER").ok().as_deref() , env::var("BROWSER_COMMAND").ok().as_deref()) {
+        Ok(launcher) => {
+            println!("[BROWSER] tabs open with {}" , launcher.name());
+            Some(launcher)
+        }
+        Err(e) => {
+            println!("[BROWSER] {} , incoming tabs will be dropped" , e);
+            None
         }
     };
 
-    let reader = BufReader::new(stream);
-    let mut lines = reader.lines();
+    let (local_tx, _) = broadcast::channel::<LocalEvent>(16);
+    let _keep_local_alive = local_tx.clone();
 
-    while let Some(line) = lines.next_line().await? {
-        match serde_json::from_str::<GlobalMsg>(&line) {
-            Ok(GlobalMsg::Tabs { tabs, time }) => {
-                println!("Sent time: {}", time);
-                let now = time_now_ms();
-                let sent_time: u128 = time.parse().unwrap();
-                println!("Elapsed: {} ns", now - sent_time);
-                println!("Elapsed: {:.3} ms", (now - sent_time) as f64 / 1_000_000.0);
+    // [edge_checker] ----- local_channel ----> ws
+    //chrome_ext registers here , the edge checker asks it who owns the front window
+    let registry = Registry::default();
+    let extensions = Extensions::new(local_tx.clone() , registry);
 
-                open_chrome(&tabs);
+    {
+        let extensions = extensions.clone();
+        edge_check(EdgeConfig::default() , move |event| {
+
+            // edge_checker ----- [local_channel] ----> ws
+            extensions.get_tabs(event);
+        });
+    }
+
+    //chrome_ext ---- ws ----> [forwarder] ---- tabs_channel ----> tcp
+    let (tabs_tx , tabs_rx) = mpsc::channel::<ClientMsg>(32);
+    //WS_LISTEN to move it , EXTENSION_IDS ( comma separated , required ) names our extension , WS_TOKEN locks it down further
+    let ws_addr = match env::var("WS_LISTEN") {
+        Ok(addr) => addr.parse::<SocketAddr>().map_err(|e| anyhow::anyhow!("WS_LISTEN '{}' : {}" , addr , e))?,
+        Err(_) => SocketAddr::from(([127, 0, 0, 1], 24810)),
+    };
+    let guard = WsGuard {
+        extension_ids : list_env("EXTENSION_IDS"),
+        token : env::var("WS_TOKEN").ok().filter(|t| !t.is_empty()),
+    };
+    //URL_* ( comma separated ) narrow what may be sent and opened , unset = http / https only
+    let policy = UrlPolicy {
+        schemes : env::var("URL_SCHEMES").map(|_| list_env("URL_SCHEMES")).unwrap_or_else(|_| UrlPolicy::default().schemes),
+        allow_domains : list_env("URL_ALLOW_DOMAINS"),
+        deny_domains : list_env("URL_DENY_DOMAINS"),
+        allow_patterns : Pattern::list(&list_env("URL_ALLOW_PATTERNS")).map_err(|e| anyhow::anyhow!("URL_ALLOW_PATTERNS : {}" , e))?,
+        deny_patterns : Pattern::list(&list_env("URL_DENY_PATTERNS")).map_err(|e| anyhow::anyhow!("URL_DENY_PATTERNS : {}" , e))?,
+    };
+    tokio::spawn(local_ws::listen(ws_addr , extensions.clone() , tabs_tx , guard , policy.clone()));
+
+    let mut local = Local {
+        extensions,
+        browser,
+        policy,
+        tabs_rx,
+        pending : Pending::new(Retry::default()),
+        replies : Replies::default(),
+    };
+
+    //supervisor , every way a link can end comes back here and waits before the next try
+    loop {
+        let started = Instant::now();
+        let outcome = match open_link(env_transport).await {
+            Ok((mut reader , server)) => {
+                //local computer ---- [auth] ----> server
+                match utils::handshake::authenticate(&mut reader , &server).await {
+                    Ok(session) => {
+                        println!("[TCP][AUTH] authenticated with '{}'" , server.hostname);
+                        println!("[LINK] up with '{}'" , server.hostname);
+                        run(reader , &server , session , &mut local).await
+                    }
+                    Err(e) => Err(e),
+                }
             }
-            Err(e) => println!("Decode json Err: {}", e),
+            Err(e) => Err(e),
+        };
+
+        let lasted = started.elapsed();
+        match outcome {
+            Ok(()) => println!("[LINK] down after {}s : server closed the link" , lasted.as_secs()),
+            Err(e) => println!("[LINK] down after {}s : {}" , lasted.as_secs() , e),
+        }
+        if lasted >= STABLE_AFTER {
+            backoff.reset();
+        }
+
+        let wait = backoff.next();
+        println!("[LINK] reconnecting in {:.1}s (attempt {})" , wait.as_secs_f64() , backoff.attempt());
+        wait_offline(wait , &mut local).await;
+    }
+}
+
+//nothing to send tabs to while we're down , they wait in `pending` and run() sends them first thing on the next link
+async fn wait_offline(wait : Duration , local : &mut Local) {
+    let until = Instant::now() + wait;
+    loop {
+        tokio::select! {
+            _ = sleep_until(until) => return,
+            Some(ClientMsg::Tabs { tabs, edge, window, .. }) = local.tabs_rx.recv() => {
+                let id = delivery::next_id();
+                let count = tabs.len();
+                match delivery::frame_json(&GlobalMsg::Tabs { id, tabs, time: time_now(), window }) {
+                    Ok(json) => {
+                        while local.pending.len() >= MAX_OFFLINE {
+                            let Some((old , tabs)) = local.pending.drop_oldest() else {
+                                break;
+                            };
+                            println!("[LINK] holding {} throws already , dropping the oldest ( {} tabs , id {} )" , MAX_OFFLINE , tabs , old);
+                        }
+                        local.pending.track(id , json , count , std::time::Instant::now());
+                        println!("[LINK] not connected , holding {} tabs from {} edge until the link is back" , count , edge);
+                    }
+                    Err(e) => println!("[LINK] not connected and can't hold {} tabs from {} edge : {}" , count , edge , e),
+                }
+            }
+        }
+    }
+}
+
+//one authenticated link , Ok = the server closed it , Err = anything else that ended it
+async fn run(reader : BufReader<BoxLink> , server : &Hello , mut session : Session , local : &mut Local) -> anyhow::Result<()> {
+    let (reader , mut writer) = tokio::io::split(reader);
+    let mut frames = FrameReader::new(reader);
+
+    //throws the last link never got an answer for and throws made while we were down , same ids so the server can tell a repeat
+    let unanswered = local.pending.unanswered();
+    if !unanswered.is_empty() {
+        println!("[LINK] sending {} unanswered throws again" , unanswered.len());
+    }
+    for line in unanswered {
+        write_frame(&mut writer , &session.seal(&line)).await?;
+        if let Ok(GlobalMsg::Tabs { id, tabs, .. }) = serde_json::from_str::<GlobalMsg>(&line) {
+            local.pending.track(id , line , tabs.len() , std::time::Instant::now());
         }
     }
 
-    Ok(())
-    
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    let mut last_seen = Instant::now();
+
+    loop {
+        //nothing pending = a timer that never fires
+        let due = local.pending.next_due().map(Instant::from_std).unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
+
+        tokio::select! {
+            //server ---- [TCP] ----> local computer ---- chrome
+            line = frames.next_frame() => {
+                let Some(line) = line? else {
+                    println!("[TCP] server disconnect");
+                    return Ok(());
+                };
+                last_seen = Instant::now();
+                let line = session.open(&line).map_err(|e| anyhow::anyhow!("[TCP][AUTH] dropping link , bad line from server : {}" , e))?;
+
+                match serde_json::from_str::<GlobalMsg>(&line) {
+                    Ok(GlobalMsg::Tabs { id, tabs, time, window }) => {
+                        println!("Sent time: {}", time);
+                        //only logged , an odd field or a server clock ahead of ours must not take the client down
+                        match time.parse::<u128>().ok().and_then(|sent_time| time_now_ms().checked_sub(sent_time)) {
+                            Some(elapsed) => {
+                                println!("Elapsed: {} ns", elapsed);
+                                println!("Elapsed: {:.3} ms", elapsed as f64 / 1_000_000.0);
+                            }
+                            None => println!("[TCP] tabs {} sent @ '{}' , can't tell how long ago" , id , time),
+                        }
+
+                        //a retry of something already opened gets the old answer , not a second window
+                        let reply = match local.replies.get(id) {
+                            Some(reply) => {
+                                println!("[TCP] server sent tabs {} again , answering again" , id);
+                                reply.clone()
+                            }
+                            None => {
+                                let reply = delivery::open(&local.extensions , local.browser.as_deref() , &local.policy , id , tabs , window).await;
+                                local.replies.remember(id , reply.clone());
+                                reply
+                            }
+                        };
+
+                        //local computer ---- [ack / nack] ----> server
+                        let json = serde_json::to_string(&reply)?;
+                        write_frame(&mut writer , &session.seal(&json)).await?;
+                    }
+                    Ok(GlobalMsg::Ping) => {
+                        let json = serde_json::to_string(&GlobalMsg::Pong)?;
+                        write_frame(&mut writer , &session.seal(&json)).await?;
+                    }
+                    Ok(GlobalMsg::Pong) => {}
+                    Ok(answer) => match local.pending.answer(&answer , std::time::Instant::now()) {
+                        Some(outcome) => println!("[TCP] '{}' {}" , server.hostname , outcome),
+                        None => chrome_leap_core::debug!("[TCP] late or unknown answer from '{}' : {:?}" , server.hostname , answer),
+                    },
+                    Err(e) => println!("Decode json Err: {}", e),
+                }
+            }
+
+            //local computer ---- [ping] ----> server , and give up on a server that went quiet
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() >= DEAD_AFTER {
+                    anyhow::bail!("nothing from '{}' in {}s , link is half open" , server.hostname , DEAD_AFTER.as_secs());
+                }
+                let json = serde_json::to_string(&GlobalMsg::Ping)?;
+                write_frame(&mut writer , &session.seal(&json)).await?;
+            }
+
+            //chrome_ext ---- ws ----> forwarder ---- [TCP] ----> server
+            //only one peer here so every edge goes to the server
+            Some(msg) = local.tabs_rx.recv() => {
+                match msg {
+                    ClientMsg::Tabs { tabs, edge, window, .. } => {
+                        if !server.has_capability(CAP_OPEN_TABS) {
+                            println!("[TCP] '{}' can't open tabs , dropping {} tabs" , server.hostname , tabs.len());
+                            continue;
+                        }
+
+                        let id = delivery::next_id();
+                        let count = tabs.len();
+                        //too big for a frame is this throw's problem , not the link's
+                        let json = match delivery::frame_json(&GlobalMsg::Tabs { id, tabs, time: time_now(), window }) {
+                            Ok(json) => json,
+                            Err(e) => {
+                                println!("[TCP] not sending {} tabs from {} edge : {}" , count , edge , e);
+                                continue;
+                            }
+                        };
+                        write_frame(&mut writer , &session.seal(&json)).await?;
+                        local.pending.track(id , json , count , std::time::Instant::now());
+                        println!("[TCP] sent tabs from {} edge to '{}'" , edge , server.hostname);
+                    }
+                    //local_ws keeps these for itself
+                    ClientMsg::Register { .. } | ClientMsg::Opened { .. } => {}
+                }
+            }
+
+            //no ack in time ---- [TCP] ----> server , same line again
+            _ = sleep_until(due) => {
+                for due in local.pending.poll(std::time::Instant::now()) {
+                    match due {
+                        Due::Resend { id , line , attempt } => {
+                            println!("[TCP] no answer from '{}' for tabs {} , sending again (try {})" , server.hostname , id , attempt);
+                            write_frame(&mut writer , &session.seal(&line)).await?;
+                        }
+                        Due::GaveUp { id , tabs , attempts } => {
+                            println!("[TCP] '{}' never answered for {} tabs ( id {} , {} tries ) , giving up" , server.hostname , tabs , id , attempts);
+                        }
+                    }
+                }
+            }
+        }
+    }
 }
@@ -0,0 +1,91 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+//wait between reconnects , doubles up to `max` , half of it random so many clients don't hit a restarted server together
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base : Duration,
+    max : Duration,
+    attempt : u32,
+}
+
+impl Backoff {
+    pub fn new(base : Duration , max : Duration) -> Backoff {
+        Backoff { base , max , attempt : 0 }
+    }
+
+    //how many waits since the last reset
+    pub fn attempt(&self) -> u32 {
+        self.attempt
+    }
+
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+
+    //upper bound of the next wait , before jitter
+    pub fn ceiling(&self) -> Duration {
+        let factor = 1u32.checked_shl(self.attempt).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    pub fn next(&mut self) -> Duration {
+        let wait = jitter(self.ceiling() , random_unit());
+        self.attempt = self.attempt.saturating_add(1);
+        wait
+    }
+}
+
+//somewhere in [ceiling / 2 , ceiling] , `unit` in [0 , 1)
+fn jitter(ceiling : Duration , unit : f64) -> Duration {
+    let half = ceiling / 2;
+    half + half.mul_f64(unit.clamp(0.0 , 1.0))
+}
+
+//no rand crate for one number , RandomState is seeded per instance
+fn random_unit() -> f64 {
+    let bits = RandomState::new().build_hasher().finish();
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_the_cap() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        let mut ceilings = Vec::new();
+        for _ in 0..7 {
+            ceilings.push(backoff.ceiling().as_secs());
+            backoff.next();
+        }
+        assert_eq!(ceilings, vec![1, 2, 4, 8, 16, 30, 30]);
+
+        backoff.reset();
+        assert_eq!(backoff.ceiling(), Duration::from_secs(1));
+        assert_eq!(backoff.attempt(), 0);
+    }
+
+    #[test]
+    fn jitter_stays_in_the_upper_half() {
+        let ceiling = Duration::from_secs(8);
+        assert_eq!(jitter(ceiling, 0.0), Duration::from_secs(4));
+        assert_eq!(jitter(ceiling, 1.0), ceiling);
+
+        let mut backoff = Backoff::new(Duration::from_secs(8), Duration::from_secs(8));
+        for _ in 0..50 {
+            let wait = backoff.next();
+            assert!(wait >= Duration::from_secs(4) && wait <= ceiling, "{:?}", wait);
+        }
+    }
+
+    #[test]
+    fn huge_attempts_do_not_overflow() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        backoff.attempt = u32::MAX;
+        assert_eq!(backoff.ceiling(), Duration::from_secs(30));
+        backoff.next();
+    }
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+
+use chrome_leap_core::discovery::{self, Peer, ServiceDaemon};
+
+//long enough for every server on the lan to answer once
+const BROWSE_TIME: Duration = Duration::from_secs(3);
+
+//local computer ---- [mdns browse] ----> lan , every chrome_leap server that answered
+pub async fn peers() -> anyhow::Result<Vec<Peer>> {
+    let daemon = ServiceDaemon::new().map_err(|e| anyhow!("can't start mdns : {}", e))?;
+    let peers = discovery::browse(&daemon, BROWSE_TIME).await;
+    let _ = daemon.shutdown();
+    peers
+}
+
+pub fn print(peers : &[Peer]) {
+    for peer in peers {
+        let addr = peer.addr().map(|a| a.to_string()).unwrap_or_else(|| "no address".to_string());
+        println!(
+            "[MDNS] '{}' @ {} ({} , fingerprint : {})" ,
+            peer.hostname , addr , peer.transport.as_str() , peer.fingerprint.as_deref().unwrap_or("-")
+        );
+    }
+}
+
+//one server on the lan = that one , more than one = SERVER=<hostname> says which
+pub fn pick(peers : Vec<Peer> , wanted : Option<&str>) -> anyhow::Result<Peer> {
+    if let Some(wanted) = wanted {
+        return peers
+            .into_iter()
+            .find(|p| p.hostname.eq_ignore_ascii_case(wanted))
+            .ok_or_else(|| anyhow!("no server called '{}' on the lan , run `chrome_leap-client discover`", wanted));
+    }
+
+    match peers.len() {
+        0 => bail!("no server found on the lan , set PORT=<server ip> to connect by hand"),
+        1 => Ok(peers.into_iter().next().unwrap()),
+        n => bail!("{} servers found on the lan , set SERVER=<hostname> to pick one", n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrome_leap_core::transport::Transport;
+
+    fn peer(hostname : &str) -> Peer {
+        Peer {
+            instance: format!("{}._chromeleap._tcp.local.", hostname),
+            hostname: hostname.to_string(),
+            addrs: vec!["192.168.1.10".parse().unwrap()],
+            port: 24811,
+            transport: Transport::Tcp,
+            fingerprint: None,
+            version: None,
+        }
+    }
+
+    #[test]
+    fn single_server_is_picked() {
+        assert_eq!(pick(vec![peer("desktop")], None).unwrap().hostname, "desktop");
+    }
+
+    #[test]
+    fn several_servers_need_a_name() {
+        let peers = vec![peer("desktop"), peer("laptop")];
+        assert!(pick(peers.clone(), None).unwrap_err().to_string().contains("SERVER="));
+        assert_eq!(pick(peers, Some("Laptop")).unwrap().hostname, "laptop");
+    }
+
+    #[test]
+    fn nothing_found() {
+        assert!(pick(vec![], None).is_err());
+        assert!(pick(vec![peer("desktop")], Some("laptop")).is_err());
+    }
+}
@@ -0,0 +1,114 @@
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use tokio::io::BufReader;
+
+use chrome_leap_core::auth::{self, Pairing, Role, Session, SessionKeys, KEYS_DIR};
+use chrome_leap_core::transport::{recv_msg, send_msg, BoxLink};
+use chrome_leap_proto::handshake::{HandshakeMsg, Hello, CAP_OPEN_TABS};
+use chrome_leap_proto::AuthMsg;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+//the server waits this long for the code to be typed
+const PAIR_TIMEOUT: Duration = Duration::from_secs(120);
+
+pub fn local_hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+//DEVICE_ID= tells two clients on one host apart , the server's config.toml uses this name
+pub fn device_id() -> String {
+    std::env::var("DEVICE_ID")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+        .unwrap_or_else(local_hostname)
+}
+
+//local computer ---- [hello] ----> server ---- [hello_ack / hello_reject] ----> local computer
+pub async fn connect(stream : &mut BufReader<BoxLink>) -> anyhow::Result<Hello> {
+
+    let hello = Hello::new(local_hostname(), &[CAP_OPEN_TABS]).with_device_id(device_id());
+    send_msg(stream, &HandshakeMsg::Hello(hello)).await?;
+
+    match recv_msg::<HandshakeMsg>(stream, HANDSHAKE_TIMEOUT).await {
+        Ok(HandshakeMsg::HelloAck(server)) => {
+            //the server checked us , check it back
+            server.check().map_err(|reason| anyhow!(reason))?;
+            Ok(server)
+        }
+        Ok(HandshakeMsg::HelloReject { version, reason }) => {
+            bail!("server (protocol v{}) rejected us : {}", version, reason)
+        }
+        Ok(HandshakeMsg::Hello(_)) | Err(_) => {
+            bail!("server did not answer hello , it is probably older than protocol v2")
+        }
+    }
+}
+
+//local computer ---- [auth] ----> server , both sides prove they hold the paired secret
+pub async fn authenticate(stream : &mut BufReader<BoxLink> , server : &Hello) -> anyhow::Result<Session> {
+
+    let Some(psk) = auth::load_psk(Path::new(KEYS_DIR), server.id())? else {
+        bail!("not paired with '{}' , run `chrome_leap-client pair` first", server.hostname);
+    };
+
+    let client_nonce = auth::random::<16>();
+    send_msg(stream, &AuthMsg::Auth { nonce: hex::encode(client_nonce) }).await?;
+
+    let server_nonce = match recv_msg::<AuthMsg>(stream, HANDSHAKE_TIMEOUT).await? {
+        AuthMsg::AuthChallenge { nonce } => hex::decode(nonce)?,
+        AuthMsg::AuthFail { reason } => bail!("server refused us : {}", reason),
+        other => bail!("expected auth_challenge , got {:?}", other),
+    };
+
+    let keys = SessionKeys::derive(&psk, &client_nonce, &server_nonce);
+    send_msg(stream, &AuthMsg::AuthProof { mac: hex::encode(keys.proof(Role::Client)) }).await?;
+
+    match recv_msg::<AuthMsg>(stream, HANDSHAKE_TIMEOUT).await? {
+        AuthMsg::AuthOk { mac } if keys.check_proof(Role::Client, &hex::decode(&mac)?) => Ok(keys.session(Role::Client)),
+        AuthMsg::AuthOk { .. } => bail!("'{}' does not hold our pairing secret , refusing it", server.hostname),
+        AuthMsg::AuthFail { reason } => bail!("server refused us : {}", reason),
+        other => bail!("expected auth_ok , got {:?}", other),
+    }
+}
+
+//local computer ---- [pair_request] ----> server , the server shows a code and we type it here
+pub async fn pair(stream : &mut BufReader<BoxLink> , server : &Hello) -> anyhow::Result<()> {
+
+    send_msg(stream, &AuthMsg::PairRequest {}).await?;
+
+    let server_pub = match recv_msg::<AuthMsg>(stream, HANDSHAKE_TIMEOUT).await? {
+        AuthMsg::PairChallenge { pub_key } => hex::decode(pub_key)?,
+        AuthMsg::AuthFail { reason } => bail!("server refused pairing : {}", reason),
+        other => bail!("expected pair_challenge , got {:?}", other),
+    };
+
+    println!("[PAIR] enter the code shown on '{}' :" , server.hostname);
+    let code = tokio::task::spawn_blocking(|| {
+        let mut code = String::new();
+        std::io::stdin().read_line(&mut code).map(|_| code)
+    })
+    .await??;
+
+    //our half only exists once the code does
+    let ours = Pairing::new(Role::Client, &code, &device_id(), server.id());
+    let pub_key = hex::encode(ours.public_key());
+    let keys = ours.finish(&server_pub)?;
+    send_msg(stream, &AuthMsg::PairConfirm { pub_key, mac: hex::encode(keys.confirm_mac(Role::Client)) }).await?;
+
+    match recv_msg::<AuthMsg>(stream, PAIR_TIMEOUT).await? {
+        AuthMsg::PairOk { mac } if keys.check_confirm(Role::Client, &hex::decode(&mac)?) => {
+            //running `pair` again is how the client re-pairs , so an old secret is replaced
+            auth::save_psk(Path::new(KEYS_DIR), server.id(), &keys.psk, true)?;
+            println!("[PAIR] paired with '{}'" , server.hostname);
+            Ok(())
+        }
+        AuthMsg::PairOk { .. } => bail!("'{}' answered with a bad confirmation , not saving anything", server.hostname),
+        AuthMsg::AuthFail { reason } => bail!("pairing failed : {}", reason),
+        other => bail!("expected pair_ok , got {:?}", other),
+    }
+}
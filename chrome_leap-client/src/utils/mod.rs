@@ -1,2 +1,4 @@
-pub mod chrome;
-pub mod os_check;
\ No newline at end of file
+pub mod backoff;
+pub mod discover;
+pub mod handshake;
+pub mod quic;
\ No newline at end of file
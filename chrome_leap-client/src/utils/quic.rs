@@ -0,0 +1,148 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use quinn::crypto::rustls::QuicClientConfig;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+
+use chrome_leap_core::auth::file_stem;
+use chrome_leap_core::transport::{self, QuicLink, ALPN, SERVER_NAME};
+
+//the server cert is self-signed , so instead of a CA we trust exactly one sha256 fingerprint
+#[derive(Debug)]
+struct PinnedCert {
+    fingerprint : String,
+    provider : Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedCert {
+    fn verify_server_cert(
+        &self,
+        end_entity : &CertificateDer<'_>,
+        _intermediates : &[CertificateDer<'_>],
+        _server_name : &ServerName<'_>,
+        _ocsp_response : &[u8],
+        _now : UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let got = transport::fingerprint(end_entity);
+        if got == self.fingerprint {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(format!(
+                "server certificate {} does not match pinned {}",
+                got, self.fingerprint
+            )))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message : &[u8],
+        cert : &CertificateDer<'_>,
+        dss : &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message : &[u8],
+        cert : &CertificateDer<'_>,
+        dss : &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+//mdns is unauthenticated , so an advertised fingerprint is only trusted the first time we see that server
+//after that it has to stay the same , a new one is refused until the stored pin is deleted
+pub fn trusted_fingerprint(dir : &Path , hostname : &str , advertised : Option<&str>) -> anyhow::Result<String> {
+    let path = dir.join(format!("{}.fingerprint" , file_stem(hostname)));
+    let advertised = advertised.map(|f| f.trim().to_ascii_lowercase());
+
+    let known = match fs::read_to_string(&path) {
+        Ok(text) => Some(text.trim().to_ascii_lowercase()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => bail!("read {} : {}" , path.display() , e),
+    };
+
+    match (known , advertised) {
+        (Some(known) , Some(advertised)) if known != advertised => bail!(
+            "'{}' now advertises certificate {} but {} was pinned on first use , delete {} if the server really got a new one" ,
+            hostname , advertised , known , path.display()
+        ),
+        (Some(known) , _) => Ok(known),
+        (None , Some(advertised)) => {
+            fs::create_dir_all(dir).with_context(|| format!("create {}" , dir.display()))?;
+            fs::write(&path , &advertised).with_context(|| format!("write {}" , path.display()))?;
+            println!("[QUIC] first connection to '{}' , pinned its certificate {} in {}" , hostname , advertised , path.display());
+            Ok(advertised)
+        }
+        (None , None) => bail!("'{}' did not advertise a fingerprint , set SERVER_FINGERPRINT" , hostname),
+    }
+}
+
+//local computer ---- quic ( pinned cert ) ----> server , one bi stream for everything
+pub async fn connect(addr : &str , fingerprint : &str) -> anyhow::Result<QuicLink> {
+    let remote = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| anyhow!("{} does not resolve", addr))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ClientConfig::builder_with_provider(provider.clone())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(PinnedCert {
+            fingerprint : fingerprint.trim().to_ascii_lowercase(),
+            provider,
+        }))
+        .with_no_client_auth();
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+
+    let mut client_config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?));
+    client_config.transport_config(transport::quic_transport_config());
+
+    let bind = if remote.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+    let mut endpoint = quinn::Endpoint::client(bind.parse().unwrap())?;
+    endpoint.set_default_client_config(client_config);
+
+    let conn = endpoint
+        .connect(remote, SERVER_NAME)?
+        .await
+        .with_context(|| format!("quic connect to {}", remote))?;
+    let streams = conn.open_bi().await?;
+
+    Ok(QuicLink::new(conn, streams, Some(endpoint)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_fingerprint_sticks() {
+        let dir = std::env::temp_dir().join(format!("chrome_leap-pins-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert!(trusted_fingerprint(&dir, "desk", None).is_err());
+        assert_eq!(trusted_fingerprint(&dir, "desk", Some("AB01")).unwrap(), "ab01");
+        assert_eq!(trusted_fingerprint(&dir, "desk", Some("ab01")).unwrap(), "ab01");
+        //nothing advertised this time , the pin still works
+        assert_eq!(trusted_fingerprint(&dir, "desk", None).unwrap(), "ab01");
+
+        //whoever answers mdns with another certificate is refused
+        assert!(trusted_fingerprint(&dir, "desk", Some("ffff")).unwrap_err().to_string().contains("pinned on first use"));
+        assert_eq!(trusted_fingerprint(&dir, "other", Some("ffff")).unwrap(), "ffff");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
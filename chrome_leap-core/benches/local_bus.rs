@@ -0,0 +1,74 @@
+//! old "get_tabs-right@0.250" strings vs LocalEvent on local_channel
+//!
+//! both go edge checker ---- broadcast ----> forwarder ---- json for chrome_ext , the part in between is what's measured
+//! no criterion , a plain loop is enough to answer the old TODO
+//!
+//! cargo bench -p chrome_leap-core --bench local_bus
+
+use std::hint::black_box;
+use std::time::{Duration, Instant, SystemTime};
+
+use tokio::sync::broadcast;
+
+use chrome_leap_core::local_ws::LocalEvent;
+use chrome_leap_core::os_check::{ActiveWindow, Edge, EdgeEvent, EdgeHit, Monitor};
+use chrome_leap_proto::ServerMsg;
+
+const ROUNDS: u32 = 200_000;
+
+fn event() -> EdgeEvent {
+    EdgeEvent {
+        hit : EdgeHit { edge : Edge::Right , at : 0.25 },
+        cursor : (1919.0 , 270.0),
+        monitor : Monitor { width : 1920 , height : 1080 },
+        time : SystemTime::now(),
+        window : ActiveWindow { title : "Inbox - Google Chrome".to_string() , app : "Google Chrome".to_string() },
+    }
+}
+
+//what handle_ws did before , split on - and build the ws message
+fn from_string(msg : &str) -> Option<ServerMsg> {
+    let msg_type = msg.split('-').next()?;
+    if msg_type != "get_tabs" {
+        return None;
+    }
+    let edge = msg.split('-').nth(1).unwrap_or("");
+    Some(ServerMsg::GetTabs { id : 0 , edge : edge.to_string() })
+}
+
+fn run(name : &str , mut round : impl FnMut()) -> Duration {
+    //warm up so the first allocations don't count
+    for _ in 0..ROUNDS / 10 {
+        round();
+    }
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        round();
+    }
+    let took = start.elapsed();
+    println!("{:<8} {:>8.1} ns / event ( {} events in {:?} )" , name , took.as_nanos() as f64 / ROUNDS as f64 , ROUNDS , took);
+    took
+}
+
+fn main() {
+    let event = event();
+
+    let (string_tx , mut string_rx) = broadcast::channel::<String>(16);
+    let string = run("string" , || {
+        string_tx.send(format!("get_tabs-{}" , event.hit.to_token())).unwrap();
+        let msg = string_rx.try_recv().unwrap();
+        let json = serde_json::to_string(&from_string(&msg).unwrap()).unwrap();
+        black_box(json);
+    });
+
+    let (typed_tx , mut typed_rx) = broadcast::channel::<LocalEvent>(16);
+    let typed = run("typed" , || {
+        typed_tx.send(LocalEvent::Edge { id : 1 , event : event.clone() , to : None }).unwrap();
+        let msg = typed_rx.try_recv().unwrap();
+        let json = serde_json::to_string(&msg.to_server_msg()).unwrap();
+        black_box(json);
+    });
+
+    println!("typed / string = {:.2}" , typed.as_secs_f64() / string.as_secs_f64());
+}
@@ -0,0 +1,470 @@
+//! crypto behind pairing and the authenticated session ( wire messages live in chrome_leap_proto::auth )
+//!
+//! pairing : spake2 on ristretto255 , both halves are blinded with the short code so a man in the middle gets one guess
+//!           per attempt instead of an offline search , each side then proves it ended with the same key ,
+//!           the key is bound to both device ids and both halves so it can't be replayed to another pair of machines
+//! session : hkdf( psk , both nonces ) -> proof key + one hmac key per direction , every line is `seq mac json`
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use ring::rand::{SecureRandom, SystemRandom};
+use ring::{hkdf, hmac};
+use sha2::{Digest, Sha256, Sha512};
+
+pub const PSK_LEN: usize = 32;
+pub type Psk = [u8; PSK_LEN];
+
+/// where paired secrets live , next to config.toml / .env
+pub const KEYS_DIR: &str = "chrome_leap-keys";
+
+/// how many digits the pairing code has
+pub const CODE_LEN: usize = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+impl Role {
+    fn label(&self) -> &'static [u8] {
+        match self {
+            Role::Client => b"chrome_leap client",
+            Role::Server => b"chrome_leap server",
+        }
+    }
+
+    fn other(&self) -> Role {
+        match self {
+            Role::Client => Role::Server,
+            Role::Server => Role::Client,
+        }
+    }
+}
+
+pub fn random<const N: usize>() -> [u8; N] {
+    let mut out = [0u8; N];
+    SystemRandom::new().fill(&mut out).expect("system rng failed");
+    out
+}
+
+/// "042917" , shown on the server , typed on the client
+pub fn pairing_code() -> String {
+    let n = u32::from_be_bytes(random::<4>()) % 10u32.pow(CODE_LEN as u32);
+    format!("{:0width$}", n, width = CODE_LEN)
+}
+
+/// people type "042 917" or "042-917"
+pub fn normalize_code(code: &str) -> String {
+    code.chars().filter(|c| c.is_ascii_digit()).collect()
+}
+
+struct Len(usize);
+
+impl hkdf::KeyType for Len {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+fn derive<const N: usize>(salt: &[u8], ikm: &[u8], info: &[&[u8]]) -> [u8; N] {
+    let mut out = [0u8; N];
+    hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+        .extract(ikm)
+        .expand(info, Len(N))
+        .and_then(|okm| okm.fill(&mut out))
+        .expect("hkdf output length is fixed and small");
+    out
+}
+
+//==== pairing =====
+
+//spake2 blinding points , M for the client and N for the server
+//hashed onto the curve so nobody knows their discrete log
+fn blinding(role: Role) -> RistrettoPoint {
+    match role {
+        Role::Client => RistrettoPoint::hash_from_bytes::<Sha512>(b"chrome_leap spake2 M"),
+        Role::Server => RistrettoPoint::hash_from_bytes::<Sha512>(b"chrome_leap spake2 N"),
+    }
+}
+
+fn code_scalar(code: &str) -> Scalar {
+    Scalar::hash_from_bytes::<Sha512>([&b"chrome_leap pair code "[..], normalize_code(code).as_bytes()].concat().as_slice())
+}
+
+//sha256 over every part with its length in front , so "ab" + "c" and "a" + "bc" differ
+fn transcript(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hash = Sha256::new();
+    hash.update(b"chrome_leap pair transcript");
+    for part in parts {
+        hash.update((part.len() as u64).to_be_bytes());
+        hash.update(part);
+    }
+    hash.finalize().into()
+}
+
+/// one side of a pairing exchange , single use
+pub struct Pairing {
+    role: Role,
+    secret: Scalar,
+    code: Scalar,
+    public: [u8; 32],
+    client_id: String,
+    server_id: String,
+}
+
+pub struct PairKeys {
+    confirm: hmac::Key,
+    pub psk: Psk,
+}
+
+impl Pairing {
+    /// our half , secret * G + code * M ( or N ) , worthless to anyone who doesn't know the code
+    /// client_id / server_id are the ids both sides key the secret by ( `Hello::id` )
+    pub fn new(role: Role, code: &str, client_id: &str, server_id: &str) -> Pairing {
+        let secret = Scalar::from_bytes_mod_order_wide(&random::<64>());
+        let code = code_scalar(code);
+        let public = (RistrettoPoint::mul_base(&secret) + blinding(role) * code).compress().to_bytes();
+        Pairing { role, secret, code, public, client_id: client_id.to_string(), server_id: server_id.to_string() }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public
+    }
+
+    /// both sides end up with the same keys only if they typed / showed the same code
+    pub fn finish(self, peer_public: &[u8]) -> anyhow::Result<PairKeys> {
+        let peer: [u8; 32] = peer_public.try_into().map_err(|_| anyhow!("bad pairing public key"))?;
+        let peer_point = CompressedRistretto(peer).decompress().ok_or_else(|| anyhow!("bad pairing public key"))?;
+
+        //take the peer's blinding off , what's left is only the same point on both sides if the codes match
+        let shared = (peer_point - blinding(self.role.other()) * self.code) * self.secret;
+
+        let (client_pub, server_pub) = match self.role {
+            Role::Client => (self.public, peer),
+            Role::Server => (peer, self.public),
+        };
+        let transcript = transcript(&[self.client_id.as_bytes(), self.server_id.as_bytes(), &client_pub, &server_pub]);
+        let okm: [u8; 64] = derive::<64>(b"chrome_leap pair", shared.compress().as_bytes(), &[&transcript, self.code.as_bytes()]);
+
+        let mut psk = [0u8; PSK_LEN];
+        psk.copy_from_slice(&okm[32..]);
+        Ok(PairKeys { confirm: hmac::Key::new(hmac::HMAC_SHA256, &okm[..32]), psk })
+    }
+}
+
+impl PairKeys {
+    pub fn confirm_mac(&self, role: Role) -> Vec<u8> {
+        hmac::sign(&self.confirm, role.label()).as_ref().to_vec()
+    }
+
+    /// checks the mac the *other* side sent
+    pub fn check_confirm(&self, role: Role, mac: &[u8]) -> bool {
+        hmac::verify(&self.confirm, role.other().label(), mac).is_ok()
+    }
+}
+
+//==== session =====
+
+pub struct SessionKeys {
+    proof: hmac::Key,
+    client_to_server: hmac::Key,
+    server_to_client: hmac::Key,
+}
+
+impl SessionKeys {
+    pub fn derive(psk: &Psk, client_nonce: &[u8], server_nonce: &[u8]) -> SessionKeys {
+        let salt = [client_nonce, server_nonce].concat();
+        let okm: [u8; 96] = derive::<96>(&salt, psk, &[b"chrome_leap session"]);
+        SessionKeys {
+            proof: hmac::Key::new(hmac::HMAC_SHA256, &okm[..32]),
+            client_to_server: hmac::Key::new(hmac::HMAC_SHA256, &okm[32..64]),
+            server_to_client: hmac::Key::new(hmac::HMAC_SHA256, &okm[64..]),
+        }
+    }
+
+    pub fn proof(&self, role: Role) -> Vec<u8> {
+        hmac::sign(&self.proof, role.label()).as_ref().to_vec()
+    }
+
+    /// checks the proof the *other* side sent
+    pub fn check_proof(&self, role: Role, mac: &[u8]) -> bool {
+        hmac::verify(&self.proof, role.other().label(), mac).is_ok()
+    }
+
+    pub fn session(self, role: Role) -> Session {
+        let (send, recv) = match role {
+            Role::Client => (self.client_to_server, self.server_to_client),
+            Role::Server => (self.server_to_client, self.client_to_server),
+        };
+        Session { send, recv, send_seq: 0, recv_seq: 0 }
+    }
+}
+
+/// what seal() puts in front of the json : a u64 , a sha256 hmac in hex and two spaces
+pub const SEAL_OVERHEAD: usize = 20 + 1 + 64 + 1;
+
+/// every line on an authenticated link : "<seq> <hex hmac> <json>"
+/// seq only goes up so a replayed or reordered line is refused
+pub struct Session {
+    send: hmac::Key,
+    recv: hmac::Key,
+    send_seq: u64,
+    recv_seq: u64,
+}
+
+fn signed(seq: u64, body: &str) -> Vec<u8> {
+    [&seq.to_be_bytes()[..], body.as_bytes()].concat()
+}
+
+impl Session {
+    pub fn seal(&mut self, body: &str) -> String {
+        self.send_seq += 1;
+        let tag = hmac::sign(&self.send, &signed(self.send_seq, body));
+        format!("{} {} {}", self.send_seq, hex::encode(tag.as_ref()), body)
+    }
+
+    pub fn open(&mut self, line: &str) -> anyhow::Result<String> {
+        let mut parts = line.splitn(3, ' ');
+        let (Some(seq), Some(mac), Some(body)) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("line is not signed");
+        };
+
+        let seq: u64 = seq.parse().context("bad sequence number")?;
+        let mac = hex::decode(mac).context("bad mac")?;
+
+        if seq != self.recv_seq + 1 {
+            bail!("unexpected sequence number {} , wanted {}", seq, self.recv_seq + 1);
+        }
+        hmac::verify(&self.recv, &signed(seq, body), &mac).map_err(|_| anyhow!("mac mismatch"))?;
+
+        self.recv_seq = seq;
+        Ok(body.to_string())
+    }
+}
+
+//==== key store =====
+
+/// device id ----> file name , anything outside [A-Za-z0-9._-] is %XX so two ids never share a file
+pub fn file_stem(id: &str) -> String {
+    let mut name = String::with_capacity(id.len());
+    for byte in id.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' || byte == b'.' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+fn psk_path(dir: &Path, peer: &str) -> std::path::PathBuf {
+    dir.join(format!("{}.psk", file_stem(peer)))
+}
+
+pub fn load_psk(dir: &Path, peer: &str) -> anyhow::Result<Option<Psk>> {
+    let path = psk_path(dir, peer);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let text = fs::read_to_string(&path).with_context(|| format!("read {}", path.display()))?;
+    let bytes = hex::decode(text.trim()).with_context(|| format!("{} is not hex", path.display()))?;
+    let psk: Psk = bytes
+        .try_into()
+        .map_err(|_| anyhow!("{} does not hold a {} byte key", path.display(), PSK_LEN))?;
+    Ok(Some(psk))
+}
+
+/// `replace` = the operator asked to pair this peer again , otherwise an existing key is never touched
+pub fn save_psk(dir: &Path, peer: &str, psk: &Psk, replace: bool) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let path = psk_path(dir, peer);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(replace)
+        .truncate(replace)
+        .create_new(!replace)
+        .open(&path)
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => anyhow!("'{}' is already paired ( {} )", peer, path.display()),
+            _ => anyhow!("write {} : {}", path.display(), e),
+        })?;
+    file.write_all(hex::encode(psk).as_bytes()).with_context(|| format!("write {}", path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(client_code: &str, server_code: &str) -> (PairKeys, PairKeys) {
+        pair_as(client_code, server_code, ("laptop", "desk"), ("laptop", "desk"))
+    }
+
+    //ids are ( client , server ) as each side sees them
+    fn pair_as(client_code: &str, server_code: &str, client_ids: (&str, &str), server_ids: (&str, &str)) -> (PairKeys, PairKeys) {
+        let client = Pairing::new(Role::Client, client_code, client_ids.0, client_ids.1);
+        let server = Pairing::new(Role::Server, server_code, server_ids.0, server_ids.1);
+        let client_pub = client.public_key().to_vec();
+        let server_pub = server.public_key().to_vec();
+
+        (client.finish(&server_pub).unwrap(), server.finish(&client_pub).unwrap())
+    }
+
+    fn session_pair() -> (Session, Session) {
+        let psk = random::<PSK_LEN>();
+        let (nc, ns) = (random::<16>(), random::<16>());
+        (
+            SessionKeys::derive(&psk, &nc, &ns).session(Role::Client),
+            SessionKeys::derive(&psk, &nc, &ns).session(Role::Server),
+        )
+    }
+
+    #[test]
+    fn code_has_fixed_length() {
+        for _ in 0..32 {
+            let code = pairing_code();
+            assert_eq!(code.len(), CODE_LEN);
+            assert_eq!(normalize_code(&code), code);
+        }
+        assert_eq!(normalize_code("042 917"), "042917");
+    }
+
+    #[test]
+    fn same_code_same_secret() {
+        let (client, server) = pair("042917", "042 917");
+        assert_eq!(client.psk, server.psk);
+        assert!(server.check_confirm(Role::Server, &client.confirm_mac(Role::Client)));
+        assert!(client.check_confirm(Role::Client, &server.confirm_mac(Role::Server)));
+    }
+
+    #[test]
+    fn wrong_code_is_caught() {
+        let (client, server) = pair("111111", "222222");
+        assert_ne!(client.psk, server.psk);
+        assert!(!server.check_confirm(Role::Server, &client.confirm_mac(Role::Client)));
+    }
+
+    #[test]
+    fn guessing_the_code_from_the_middle_fails() {
+        //someone in the middle answers the client with its own half made from a guess
+        let client = Pairing::new(Role::Client, "123456", "laptop", "desk");
+        let middle = Pairing::new(Role::Server, "000000", "laptop", "desk");
+        let client_pub = client.public_key().to_vec();
+        let middle_pub = middle.public_key().to_vec();
+        let (client, middle) = (client.finish(&middle_pub).unwrap(), middle.finish(&client_pub).unwrap());
+
+        assert_ne!(client.psk, middle.psk);
+        assert!(!middle.check_confirm(Role::Server, &client.confirm_mac(Role::Client)));
+    }
+
+    #[test]
+    fn bad_public_keys_are_errors() {
+        assert!(Pairing::new(Role::Server, "123456", "laptop", "desk").finish(&[0xff; 32]).is_err());
+        assert!(Pairing::new(Role::Server, "123456", "laptop", "desk").finish(&[1; 5]).is_err());
+    }
+
+    #[test]
+    fn keys_are_bound_to_both_ids() {
+        //right code , but the two sides disagree on who is pairing with whom
+        let (client, server) = pair_as("042917", "042917", ("laptop", "desk"), ("phone", "desk"));
+        assert_ne!(client.psk, server.psk);
+        assert!(!server.check_confirm(Role::Server, &client.confirm_mac(Role::Client)));
+
+        let (client, server) = pair_as("042917", "042917", ("laptop", "desk"), ("laptop", "other"));
+        assert_ne!(client.psk, server.psk);
+
+        //no splitting one id into the other
+        let (client, server) = pair_as("042917", "042917", ("lap", "topdesk"), ("laptop", "desk"));
+        assert_ne!(client.psk, server.psk);
+    }
+
+    #[test]
+    fn confirm_mac_is_not_reflectable() {
+        let (client, _) = pair("123456", "123456");
+        assert!(!client.check_confirm(Role::Client, &client.confirm_mac(Role::Client)));
+    }
+
+    #[test]
+    fn proof_needs_same_psk() {
+        let (nc, ns) = (random::<16>(), random::<16>());
+        let client = SessionKeys::derive(&[1; PSK_LEN], &nc, &ns);
+        let server = SessionKeys::derive(&[1; PSK_LEN], &nc, &ns);
+        let stranger = SessionKeys::derive(&[2; PSK_LEN], &nc, &ns);
+
+        assert!(server.check_proof(Role::Server, &client.proof(Role::Client)));
+        assert!(!server.check_proof(Role::Server, &stranger.proof(Role::Client)));
+    }
+
+    #[test]
+    fn session_round_trip_both_ways() {
+        let (mut client, mut server) = session_pair();
+        for i in 0..3 {
+            let line = client.seal(&format!("{{\"n\":{}}}", i));
+            assert_eq!(server.open(&line).unwrap(), format!("{{\"n\":{}}}", i));
+        }
+        let line = server.seal("back");
+        assert_eq!(client.open(&line).unwrap(), "back");
+    }
+
+    #[test]
+    fn session_rejects_tamper_replay_and_reflection() {
+        let (mut client, mut server) = session_pair();
+
+        let line = client.seal(r#"{"action":"tabs"}"#);
+        assert!(server.open(&line.replace("tabs", "tebs")).is_err());
+        assert!(server.open(&line).is_ok());
+        assert!(server.open(&line).is_err(), "replay");
+
+        let own = server.seal("mine");
+        assert!(server.open(&own).is_err(), "reflection");
+
+        assert!(server.open(r#"{"action":"tabs"}"#).is_err(), "unsigned");
+    }
+
+    #[test]
+    fn file_stems_dont_collide() {
+        assert_eq!(file_stem("desk-pc.lan_2"), "desk-pc.lan_2");
+        assert_eq!(file_stem("laptop/work"), "laptop%2Fwork");
+        assert_eq!(file_stem("a%2Fb"), "a%252Fb");
+        assert_ne!(file_stem("laptop/work"), file_stem("laptop_work"));
+        assert_ne!(file_stem("laptop:work"), file_stem("laptop/work"));
+    }
+
+    #[test]
+    fn psk_store_round_trip() {
+        let dir = std::env::temp_dir().join(format!("chrome_leap-keys-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(load_psk(&dir, "desk/top").unwrap(), None);
+        let psk = random::<PSK_LEN>();
+        save_psk(&dir, "desk/top", &psk, false).unwrap();
+        assert_eq!(load_psk(&dir, "desk/top").unwrap(), Some(psk));
+
+        //a second pairing can't take the id over unless the operator asked for it
+        let other = random::<PSK_LEN>();
+        assert!(save_psk(&dir, "desk/top", &other, false).unwrap_err().to_string().contains("already paired"));
+        assert_eq!(load_psk(&dir, "desk/top").unwrap(), Some(psk));
+        save_psk(&dir, "desk/top", &other, true).unwrap();
+        assert_eq!(load_psk(&dir, "desk/top").unwrap(), Some(other));
+
+        //escaped , not flattened , so these are two different devices
+        assert_eq!(load_psk(&dir, "desk_top").unwrap(), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
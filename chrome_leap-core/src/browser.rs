@@ -0,0 +1,767 @@
+//! whatever opens the tabs that come in over the link
+//!
+//! another_computer ---- tabs ----> [BrowserLauncher] ----> chrome / chromium / brave / edge / firefox / xdg-open / custom
+
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+use anyhow::{anyhow, bail};
+use url::Url;
+
+use chrome_leap_proto::{TabInfo, WindowInfo};
+
+/// how much command line one spawn may use , windows caps the whole line at 32767 chars
+pub const ARGV_BUDGET: usize = if cfg!(windows) { 30_000 } else { 128 * 1024 };
+
+/// one process spawn and how it went
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchReport {
+    /// urls this spawn was given , in window order
+    pub urls : Vec<String>,
+    pub error : Option<String>,
+}
+
+impl BatchReport {
+    pub fn ok(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// something that can open tabs , as few processes as it allows
+pub trait BrowserLauncher: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// most urls one process can take , 1 = no batching
+    fn batch_limit(&self , _incognito : bool) -> usize {
+        usize::MAX
+    }
+
+    /// the process that opens this batch , `new_window` on the first batch of a window
+    fn command(&self , tabs : &[&TabInfo] , new_window : bool , window : Option<&WindowInfo>) -> Command;
+
+    /// open one batch , Err = why it didn't , spawns `command` unless the launcher has another way
+    fn launch(&self , tabs : &[&TabInfo] , new_window : bool , window : Option<&WindowInfo>) -> Result<() , String> {
+        let mut command = self.command(tabs , new_window , window);
+        match command.spawn() {
+            Ok(child) => {
+                reap(child);
+                Ok(())
+            }
+            Err(e) => Err(format!("{} : can't run {:?} : {}" , self.name() , command.get_program() , e)),
+        }
+    }
+
+    /// one report per spawn , a failed batch doesn't stop the next one
+    fn open(&self , tabs : &[TabInfo] , window : Option<&WindowInfo>) -> Vec<BatchReport> {
+        crate::debug!("[BROWSER] {} opens {:?}" , self.name() , tabs.iter().map(|t| t.url.as_str()).collect::<Vec<_>>());
+        not_reproduced(tabs);
+
+        //a url is an argument , one that starts with - would be read as a flag
+        let (flags , ordered): (Vec<&TabInfo> , Vec<&TabInfo>) = opening_order(tabs).into_iter().partition(|t| t.url.starts_with('-'));
+        let mut reports = Vec::new();
+        if !flags.is_empty() {
+            reports.push(BatchReport {
+                urls : flags.iter().map(|t| t.url.clone()).collect(),
+                error : Some(format!("{} : not opening urls that look like command line flags" , self.name())),
+            });
+        }
+
+        //incognito tabs can't share a window with normal ones
+        let (private , normal): (Vec<&TabInfo> , Vec<&TabInfo>) = ordered.into_iter().partition(|t| t.incognito);
+
+        for group in [normal , private] {
+            let Some(incognito) = group.first().map(|t| t.incognito) else {
+                continue;
+            };
+
+            for (i , batch) in batches(&group , self.batch_limit(incognito) , ARGV_BUDGET).iter().enumerate() {
+                let error = self.launch(batch , i == 0 , window).err();
+                reports.push(BatchReport { urls : batch.iter().map(|t| t.url.clone()).collect() , error });
+            }
+        }
+        reports
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Chrome,
+    Chromium,
+    Brave,
+    Edge,
+    Firefox,
+    XdgOpen,
+}
+
+impl Kind {
+    /// detection order , xdg-open last since it opens whatever the desktop default is
+    pub const ALL: [Kind; 6] = [Kind::Chrome, Kind::Chromium, Kind::Brave, Kind::Edge, Kind::Firefox, Kind::XdgOpen];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Kind::Chrome => "chrome",
+            Kind::Chromium => "chromium",
+            Kind::Brave => "brave",
+            Kind::Edge => "edge",
+            Kind::Firefox => "firefox",
+            Kind::XdgOpen => "xdg-open",
+        }
+    }
+
+    pub fn parse(s : &str) -> Option<Kind> {
+        Kind::ALL.into_iter().find(|k| k.as_str().eq_ignore_ascii_case(s.trim()))
+    }
+
+    //names on PATH , first one found wins
+    fn programs(&self) -> &'static [&'static str] {
+        match self {
+            Kind::Chrome => &["google-chrome", "google-chrome-stable", "chrome"],
+            Kind::Chromium => &["chromium", "chromium-browser"],
+            Kind::Brave => &["brave-browser", "brave"],
+            Kind::Edge => &["microsoft-edge", "microsoft-edge-stable", "msedge"],
+            Kind::Firefox => &["firefox"],
+            Kind::XdgOpen => &["xdg-open"],
+        }
+    }
+
+    //what the installer registers under App Paths on windows
+    fn app_path_name(&self) -> Option<&'static str> {
+        match self {
+            Kind::Chrome => Some("chrome.exe"),
+            Kind::Brave => Some("brave.exe"),
+            Kind::Edge => Some("msedge.exe"),
+            Kind::Firefox => Some("firefox.exe"),
+            //chromium registers chrome.exe too , that one belongs to chrome
+            Kind::Chromium | Kind::XdgOpen => None,
+        }
+    }
+
+    //windows installs don't put browsers on PATH , relative to the program files dirs
+    fn install_paths(&self) -> &'static [&'static str] {
+        match self {
+            Kind::Chrome => &["Google\\Chrome\\Application\\chrome.exe"],
+            Kind::Chromium => &["Chromium\\Application\\chrome.exe"],
+            Kind::Brave => &["BraveSoftware\\Brave-Browser\\Application\\brave.exe"],
+            Kind::Edge => &["Microsoft\\Edge\\Application\\msedge.exe"],
+            Kind::Firefox => &["Mozilla Firefox\\firefox.exe"],
+            Kind::XdgOpen => &[],
+        }
+    }
+
+    /// the launcher for this browser if it is installed
+    pub fn find(&self) -> Option<Box<dyn BrowserLauncher>> {
+        if *self == Kind::XdgOpen && cfg!(windows) {
+            return Some(Box::new(XdgOpen));
+        }
+
+        let program = self
+            .programs()
+            .iter()
+            .find_map(|p| which(p))
+            .or_else(|| self.app_path_name().and_then(app_path))
+            .or_else(|| windows_install(self.install_paths()))?;
+
+        Some(match self {
+            Kind::Firefox => Box::new(Firefox { program }),
+            Kind::XdgOpen => Box::new(XdgOpen),
+            kind => Box::new(Chromium { kind : *kind , program }),
+        })
+    }
+}
+
+/// chrome and everything built on it , same flags
+#[derive(Debug, Clone)]
+pub struct Chromium {
+    pub kind : Kind,
+    pub program : PathBuf,
+}
+
+impl BrowserLauncher for Chromium {
+    fn name(&self) -> &str {
+        self.kind.as_str()
+    }
+
+    //later batches have no --new-window , chrome puts them in the window it just opened
+    fn command(&self , tabs : &[&TabInfo] , new_window : bool , window : Option<&WindowInfo>) -> Command {
+        let mut command = Command::new(&self.program);
+        if new_window {
+            command.arg("--new-window");
+            if let Some(window) = window {
+                command.args(window_flags(window));
+            }
+        }
+        if tabs.iter().any(|t| t.incognito) {
+            command.arg("--incognito");
+        }
+        command.args(tabs.iter().map(|t| &t.url));
+        command
+    }
+}
+
+//best effort , chrome only honours these when it opens the window
+fn window_flags(window : &WindowInfo) -> Vec<String> {
+    let mut flags = Vec::new();
+    if let (Some(left) , Some(top)) = (window.left , window.top) {
+        flags.push(format!("--window-position={},{}" , left , top));
+    }
+    if let (Some(width) , Some(height)) = (window.width , window.height) {
+        flags.push(format!("--window-size={},{}" , width , height));
+    }
+    match window.state.as_deref() {
+        Some("maximized") => flags.push("--start-maximized".to_string()),
+        Some("fullscreen") => flags.push("--start-fullscreen".to_string()),
+        _ => {}
+    }
+    flags
+}
+
+#[derive(Debug, Clone)]
+pub struct Firefox {
+    pub program : PathBuf,
+}
+
+impl BrowserLauncher for Firefox {
+    fn name(&self) -> &str {
+        "firefox"
+    }
+
+    //--private-window takes exactly one url
+    fn batch_limit(&self , incognito : bool) -> usize {
+        if incognito { 1 } else { usize::MAX }
+    }
+
+    fn command(&self , tabs : &[&TabInfo] , new_window : bool , _window : Option<&WindowInfo>) -> Command {
+        let mut command = Command::new(&self.program);
+        if tabs.iter().any(|t| t.incognito) {
+            command.arg("--private-window");
+        } else if new_window {
+            command.arg("--new-window");
+        }
+        command.args(tabs.iter().map(|t| &t.url));
+        command
+    }
+}
+
+/// the desktop's default browser , one url per call , incognito is lost
+/// it opens whatever it's handed ( files , programs , any registered scheme ) , so only web urls get that far
+/// on windows ShellExecuteW gets the url itself , no cmd and no command line in between
+#[derive(Debug, Clone)]
+pub struct XdgOpen;
+
+impl BrowserLauncher for XdgOpen {
+    fn name(&self) -> &str {
+        "xdg-open"
+    }
+
+    fn batch_limit(&self , _incognito : bool) -> usize {
+        1
+    }
+
+    fn command(&self , tabs : &[&TabInfo] , _new_window : bool , _window : Option<&WindowInfo>) -> Command {
+        let mut command = Command::new("xdg-open");
+        command.args(tabs.iter().map(|t| &t.url));
+        command
+    }
+
+    fn launch(&self , tabs : &[&TabInfo] , _new_window : bool , _window : Option<&WindowInfo>) -> Result<() , String> {
+        let url = tabs.first().map(|t| t.url.as_str()).unwrap_or_default();
+        let url = web_url(url).map_err(|reason| format!("{} : not opening '{}' : {}" , self.name() , url , reason))?;
+        if cfg!(windows) {
+            return shell_execute(&shell_target(&url));
+        }
+        let tab = TabInfo::from_url(&url);
+        let mut command = self.command(&[&tab] , false , None);
+        command.spawn().map(reap).map_err(|e| format!("{} : can't run {:?} : {}" , self.name() , command.get_program() , e))
+    }
+}
+
+//http(s) with a host , as the browser will read it , anything else could be a file or a program to the shell
+fn web_url(url : &str) -> Result<String , String> {
+    let parsed = Url::parse(url).map_err(|e| format!("not a url : {}" , e))?;
+    if !matches!(parsed.scheme() , "http" | "https") {
+        return Err(format!("scheme '{}' is not a web url" , parsed.scheme()));
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        return Err("no host".to_string());
+    }
+    Ok(parsed.into())
+}
+
+//lpFile for ShellExecuteW , nul terminated
+fn shell_target(url : &str) -> Vec<u16> {
+    url.encode_utf16().chain(Some(0)).collect()
+}
+
+#[cfg(windows)]
+fn shell_execute(target : &[u16]) -> Result<() , String> {
+    use windows::core::{w, PCWSTR};
+    use windows::Win32::Foundation::HWND;
+    use windows::Win32::UI::Shell::ShellExecuteW;
+    use windows::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+
+    let done = unsafe { ShellExecuteW(HWND::default() , w!("open") , PCWSTR(target.as_ptr()) , PCWSTR::null() , PCWSTR::null() , SW_SHOWNORMAL) };
+    //anything up to 32 is an error code , not an instance
+    let code = done.0 as isize;
+    if code > 32 {
+        Ok(())
+    } else {
+        Err(format!("xdg-open : ShellExecuteW failed ( {} )" , code))
+    }
+}
+
+#[cfg(not(windows))]
+fn shell_execute(_target : &[u16]) -> Result<() , String> {
+    Err("xdg-open : ShellExecuteW is windows only".to_string())
+}
+
+/// config.toml [browser] command
+/// `{urls}` = every url of the batch as separate args , `{url}` = one url per process , neither = urls go last
+#[derive(Debug, Clone)]
+pub struct Custom {
+    pub template : String,
+    argv : Vec<String>,
+}
+
+impl Custom {
+    pub fn new(template : &str) -> anyhow::Result<Custom> {
+        let argv = split_template(template)?;
+        if argv.is_empty() {
+            bail!("empty browser command");
+        }
+        Ok(Custom { template : template.to_string() , argv })
+    }
+}
+
+impl BrowserLauncher for Custom {
+    fn name(&self) -> &str {
+        &self.template
+    }
+
+    fn batch_limit(&self , _incognito : bool) -> usize {
+        if self.argv.iter().any(|a| a.contains("{url}")) { 1 } else { usize::MAX }
+    }
+
+    fn command(&self , tabs : &[&TabInfo] , _new_window : bool , _window : Option<&WindowInfo>) -> Command {
+        let mut args: Vec<String> = Vec::new();
+        let mut placed = false;
+        for arg in &self.argv {
+            if arg == "{urls}" {
+                args.extend(tabs.iter().map(|t| t.url.clone()));
+                placed = true;
+            } else if arg.contains("{url}") {
+                args.push(arg.replace("{url}" , tabs.first().map(|t| t.url.as_str()).unwrap_or_default()));
+                placed = true;
+            } else {
+                args.push(arg.clone());
+            }
+        }
+        if !placed {
+            args.extend(tabs.iter().map(|t| t.url.clone()));
+        }
+
+        //new() made sure there is a program
+        let mut args = args.into_iter();
+        let mut command = Command::new(args.next().unwrap_or_default());
+        command.args(args);
+        command
+    }
+}
+
+/// `command` beats `name` , no name or "auto" = first installed browser
+pub fn select(name : Option<&str> , command : Option<&str>) -> anyhow::Result<Box<dyn BrowserLauncher>> {
+    if let Some(command) = command {
+        return Ok(Box::new(Custom::new(command)?));
+    }
+
+    match name.map(str::trim).filter(|n| !n.is_empty() && !n.eq_ignore_ascii_case("auto")) {
+        None => detect(),
+        Some(name) => {
+            let kind = Kind::parse(name).ok_or_else(|| {
+                let names: Vec<&str> = Kind::ALL.iter().map(|k| k.as_str()).collect();
+                anyhow!("unknown browser '{}' , expected auto or one of {:?}" , name , names)
+            })?;
+            kind.find().ok_or_else(|| anyhow!("{} is not installed ( not on PATH )" , kind.as_str()))
+        }
+    }
+}
+
+/// first installed browser in `Kind::ALL` order
+pub fn detect() -> anyhow::Result<Box<dyn BrowserLauncher>> {
+    Kind::ALL
+        .iter()
+        .find_map(|k| k.find())
+        .ok_or_else(|| anyhow!("no browser found , install chrome or set [browser] command"))
+}
+
+/// the order the tabs had in their window
+pub fn opening_order(tabs : &[TabInfo]) -> Vec<&TabInfo> {
+    let mut ordered: Vec<&TabInfo> = tabs.iter().collect();
+    ordered.sort_by_key(|t| t.index);
+    ordered
+}
+
+/// split into spawns of at most `limit` urls and `budget` bytes of argv , order kept
+/// a single url longer than the budget still gets its own batch
+pub fn batches<'a>(tabs : &[&'a TabInfo] , limit : usize , budget : usize) -> Vec<Vec<&'a TabInfo>> {
+    let mut out: Vec<Vec<&TabInfo>> = Vec::new();
+    let mut current: Vec<&TabInfo> = Vec::new();
+    let mut used = 0;
+
+    for tab in tabs {
+        //+3 for the separator and the quotes windows may add
+        let cost = tab.url.len() + 3;
+        if !current.is_empty() && (current.len() >= limit.max(1) || used + cost > budget) {
+            out.push(std::mem::take(&mut current));
+            used = 0;
+        }
+        current.push(tab);
+        used += cost;
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+/// one line for the log , None when every batch worked
+pub fn failures(reports : &[BatchReport]) -> Option<String> {
+    let failed: Vec<&str> = reports.iter().filter_map(|r| r.error.as_deref()).collect();
+    if failed.is_empty() {
+        return None;
+    }
+    let lost: usize = reports.iter().filter(|r| !r.ok()).map(|r| r.urls.len()).sum();
+    Some(format!("{} of {} batches failed ( {} tabs ) : {}" , failed.len() , reports.len() , lost , failed.join(" ; ")))
+}
+
+//say what got lost instead of dropping it silently
+fn not_reproduced(tabs : &[TabInfo]) {
+    let pinned = tabs.iter().filter(|t| t.pinned).count();
+    let grouped = tabs.iter().filter(|t| t.group.is_some()).count();
+    if pinned > 0 || grouped > 0 {
+        crate::debug!(
+            "[BROWSER] opening from the command line , {} pins and {} grouped tabs are not kept" ,
+            pinned , grouped
+        );
+    }
+}
+
+//browsers hand the url to a running instance and exit , wait so they don't stay zombies
+fn reap(mut child : Child) {
+    std::thread::spawn(move || {
+        let _ = child.wait();
+    });
+}
+
+fn which(program : &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    let exts: Vec<String> = if cfg!(windows) {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".EXE".to_string())
+            .split(';')
+            .map(|e| e.to_ascii_lowercase())
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+
+    std::env::split_paths(&path).find_map(|dir| {
+        exts.iter()
+            .map(|ext| dir.join(format!("{}{}" , program , ext)))
+            .find(|candidate| is_executable(candidate))
+    })
+}
+
+fn windows_install(paths : &[&str]) -> Option<PathBuf> {
+    if !cfg!(windows) {
+        return None;
+    }
+    ["ProgramFiles", "ProgramFiles(x86)", "LOCALAPPDATA"]
+        .iter()
+        .filter_map(std::env::var_os)
+        .flat_map(|base| paths.iter().map(move |p| Path::new(&base).join(p)))
+        .find(|candidate| candidate.is_file())
+}
+
+//HKCU then HKLM \SOFTWARE\Microsoft\Windows\CurrentVersion\App Paths\<exe> , the default value is the full path
+#[cfg(windows)]
+fn app_path(exe : &str) -> Option<PathBuf> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Foundation::ERROR_SUCCESS;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE, RRF_RT_REG_SZ};
+
+    let key: Vec<u16> = format!("SOFTWARE\\Microsoft\\Windows\\CurrentVersion\\App Paths\\{}" , exe)
+        .encode_utf16()
+        .chain(Some(0))
+        .collect();
+
+    [HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE].into_iter().find_map(|root| {
+        let mut buf = [0u16; 1024];
+        let mut size = (buf.len() * 2) as u32;
+        let status = unsafe {
+            RegGetValueW(root , PCWSTR(key.as_ptr()) , PCWSTR::null() , RRF_RT_REG_SZ , None , Some(buf.as_mut_ptr().cast()) , Some(&mut size as *mut u32))
+        };
+        if status != ERROR_SUCCESS {
+            return None;
+        }
+        //size is in bytes and counts the trailing nul , some installers quote the path
+        let len = (size as usize / 2).saturating_sub(1).min(buf.len());
+        let path = PathBuf::from(String::from_utf16_lossy(&buf[..len]).trim_matches('"'));
+        path.is_file().then_some(path)
+    })
+}
+
+#[cfg(not(windows))]
+fn app_path(_exe : &str) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(unix)]
+fn is_executable(path : &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0).unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path : &Path) -> bool {
+    path.is_file()
+}
+
+//words split on whitespace , "double quotes" keep spaces together
+fn split_template(template : &str) -> anyhow::Result<Vec<String>> {
+    let mut argv = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quoted = false;
+
+    for c in template.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_word {
+                    argv.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            c => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+
+    if quoted {
+        bail!("unclosed \" in browser command '{}'" , template);
+    }
+    if in_word {
+        argv.push(word);
+    }
+    Ok(argv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    fn tab(url : &str , index : u32 , active : bool) -> TabInfo {
+        TabInfo { index , active , ..TabInfo::from_url(url) }
+    }
+
+    fn argv(command : &Command) -> Vec<&OsStr> {
+        std::iter::once(command.get_program()).chain(command.get_args()).collect()
+    }
+
+    fn urls(batch : &[&TabInfo]) -> Vec<String> {
+        batch.iter().map(|t| t.url.clone()).collect()
+    }
+
+    #[test]
+    fn window_order_is_kept() {
+        let tabs = vec![tab("c", 2, false), tab("a", 0, false), tab("b", 1, true)];
+        let order: Vec<&str> = opening_order(&tabs).iter().map(|t| t.url.as_str()).collect();
+        assert_eq!(order, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn batches_chunk_by_count_and_argv_size() {
+        let tabs: Vec<TabInfo> = (0..5).map(|i| tab(&format!("https://{}.example", i), i, false)).collect();
+        let ordered = opening_order(&tabs);
+
+        let all = batches(&ordered, usize::MAX, ARGV_BUDGET);
+        assert_eq!(all.len(), 1);
+        assert_eq!(urls(&all[0]).len(), 5);
+
+        let by_count = batches(&ordered, 2, ARGV_BUDGET);
+        assert_eq!(by_count.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+
+        //"https://0.example" + 3 = 20 bytes , two fit in 45
+        let by_size = batches(&ordered, usize::MAX, 45);
+        assert_eq!(by_size.iter().map(Vec::len).collect::<Vec<_>>(), vec![2, 2, 1]);
+        let flat: Vec<String> = by_size.iter().flat_map(|b| urls(b)).collect();
+        assert_eq!(flat, urls(&ordered));
+    }
+
+    #[test]
+    fn oversized_url_gets_its_own_batch() {
+        let long = "x".repeat(100);
+        let tabs = vec![tab("a", 0, false), tab(&long, 1, false), tab("b", 2, false)];
+        let ordered = opening_order(&tabs);
+        let chunks = batches(&ordered, usize::MAX, 10);
+        assert_eq!(chunks.iter().map(|b| urls(b)).collect::<Vec<_>>(), vec![vec!["a".to_string()], vec![long], vec!["b".to_string()]]);
+        assert!(batches(&[], 4, 10).is_empty());
+    }
+
+    #[test]
+    fn chromium_first_batch_opens_the_window() {
+        let brave = Chromium { kind : Kind::Brave , program : PathBuf::from("/usr/bin/brave") };
+        let (a , b) = (tab("https://a.example", 0, false), tab("https://b.example", 1, true));
+        let window = WindowInfo { left : Some(10) , top : Some(20) , width : Some(800) , height : Some(600) , state : Some("maximized".to_string()) };
+
+        assert_eq!(
+            argv(&brave.command(&[&a, &b], true, Some(&window))),
+            ["/usr/bin/brave", "--new-window", "--window-position=10,20", "--window-size=800,600", "--start-maximized", "https://a.example", "https://b.example"]
+        );
+        assert_eq!(argv(&brave.command(&[&b], false, Some(&window))), ["/usr/bin/brave", "https://b.example"]);
+
+        let private = TabInfo { incognito : true , ..TabInfo::from_url("https://a.example") };
+        assert_eq!(argv(&brave.command(&[&private], true, None)), ["/usr/bin/brave", "--new-window", "--incognito", "https://a.example"]);
+        assert_eq!(brave.name(), "brave");
+    }
+
+    #[test]
+    fn firefox_private_window() {
+        let firefox = Firefox { program : PathBuf::from("firefox") };
+        let private = TabInfo { incognito : true , ..TabInfo::from_url("https://a.example") };
+        let (x , y) = (TabInfo::from_url("x"), TabInfo::from_url("y"));
+        assert_eq!(argv(&firefox.command(&[&private], true, None)), ["firefox", "--private-window", "https://a.example"]);
+        assert_eq!(argv(&firefox.command(&[&x, &y], true, None)), ["firefox", "--new-window", "x", "y"]);
+        assert_eq!(argv(&firefox.command(&[&y], false, None)), ["firefox", "y"]);
+        assert_eq!((firefox.batch_limit(true), firefox.batch_limit(false)), (1, usize::MAX));
+    }
+
+    #[test]
+    fn custom_template() {
+        let (a , b) = (TabInfo::from_url("https://a.example"), TabInfo::from_url("https://b.example"));
+
+        let flatpak = Custom::new("flatpak run com.brave.Browser --new-window {url}").unwrap();
+        assert_eq!(flatpak.batch_limit(false), 1);
+        assert_eq!(
+            argv(&flatpak.command(&[&a], true, None)),
+            ["flatpak", "run", "com.brave.Browser", "--new-window", "https://a.example"]
+        );
+
+        let batched = Custom::new("my-browser {urls} --flag").unwrap();
+        assert_eq!(batched.batch_limit(false), usize::MAX);
+        assert_eq!(argv(&batched.command(&[&a, &b], true, None)), ["my-browser", "https://a.example", "https://b.example", "--flag"]);
+
+        let no_placeholder = Custom::new("\"/opt/my browser/run\" --flag").unwrap();
+        assert_eq!(argv(&no_placeholder.command(&[&a, &b], true, None)), ["/opt/my browser/run", "--flag", "https://a.example", "https://b.example"]);
+    }
+
+    #[test]
+    fn bad_templates_are_errors() {
+        assert!(Custom::new("   ").is_err());
+        assert!(Custom::new("\"unclosed --flag").is_err());
+    }
+
+    #[test]
+    fn select_errors_instead_of_panicking() {
+        assert!(select(Some("netscape"), None).is_err());
+        assert!(select(Some("anything"), Some("my-browser {url}")).is_ok());
+        assert_eq!(Kind::parse("Firefox"), Some(Kind::Firefox));
+    }
+
+    //what a page title or a crafted link can put in a url , each has to stay exactly one argument
+    const HOSTILE: [&str; 6] = [
+        "https://a.example/?q=1&calc.exe",
+        "https://a.example/|whoami",
+        "https://a.example/^&%COMSPEC%",
+        "https://a.example/\" --incognito \"",
+        "https://a.example/x > C:\\out.txt",
+        "https://a.example/$(id);`id`",
+    ];
+
+    #[test]
+    fn hostile_urls_stay_one_argument() {
+        let chrome = Chromium { kind : Kind::Chrome , program : PathBuf::from("C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe") };
+        let firefox = Firefox { program : PathBuf::from("firefox") };
+        for url in HOSTILE {
+            let tab = TabInfo::from_url(url);
+            assert_eq!(
+                argv(&chrome.command(&[&tab], false, None)),
+                [OsStr::new("C:\\Program Files\\Google\\Chrome\\Application\\chrome.exe"), OsStr::new(url)]
+            );
+            assert_eq!(argv(&firefox.command(&[&tab], false, None)), [OsStr::new("firefox"), OsStr::new(url)]);
+        }
+    }
+
+    #[test]
+    fn default_browser_gets_one_web_url() {
+        for url in HOSTILE {
+            let web = web_url(url).unwrap();
+            assert!(web.starts_with("https://a.example/"), "{}", web);
+            //exactly the url and its nul , nothing for a command line to split
+            let target = shell_target(&web);
+            assert_eq!(target.iter().position(|c| *c == 0), Some(target.len() - 1));
+            assert_eq!(String::from_utf16(&target[..target.len() - 1]).unwrap(), web);
+
+            let tab = TabInfo::from_url(&web);
+            assert_eq!(argv(&XdgOpen.command(&[&tab], true, None)), [OsStr::new("xdg-open"), OsStr::new(&web)]);
+        }
+        assert_eq!(web_url("https://a.example/\" --incognito \"").unwrap(), "https://a.example/%22%20--incognito%20%22");
+        assert_eq!(shell_target(&web_url("https:\\\\a.example\\x").unwrap()), "https://a.example/x\0".encode_utf16().collect::<Vec<u16>>());
+    }
+
+    #[test]
+    fn default_browser_never_gets_files_or_programs() {
+        for url in [
+            "file:///C:/Windows/System32/calc.exe",
+            "C:\\Windows\\System32\\calc.exe",
+            "calc.exe",
+            "\\\\server\\share\\run.exe",
+            "ms-settings:",
+            "javascript:alert(1)",
+            "https://",
+        ] {
+            assert!(web_url(url).is_err(), "{}", url);
+            let reports = XdgOpen.open(&[TabInfo::from_url(url)], None);
+            assert!(reports[0].error.as_deref().unwrap().contains("not opening"), "{}", url);
+        }
+    }
+
+    #[test]
+    fn flag_like_urls_are_not_spawned() {
+        let gone = Chromium { kind : Kind::Chrome , program : PathBuf::from("/nonexistent/chrome_leap-test-browser") };
+        let tabs = vec![tab("--renderer-cmd-prefix=calc", 0, false), tab("https://a.example", 1, false)];
+        let reports = gone.open(&tabs, None);
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].urls, vec!["--renderer-cmd-prefix=calc"]);
+        assert!(reports[0].error.as_deref().unwrap().contains("command line flags"));
+        //the real url still gets its own spawn , it fails here only because the browser doesn't exist
+        assert_eq!(reports[1].urls, vec!["https://a.example"]);
+        assert!(reports[1].error.as_deref().unwrap().contains("can't run"));
+    }
+
+    #[test]
+    fn one_report_per_spawn() {
+        let gone = Chromium { kind : Kind::Chrome , program : PathBuf::from("/nonexistent/chrome_leap-test-browser") };
+        let tabs = vec![
+            tab("https://a.example", 0, false),
+            TabInfo { incognito : true , ..tab("https://p.example", 1, false) },
+            tab("https://b.example", 2, true),
+        ];
+        let reports = gone.open(&tabs, None);
+
+        //normal window first , then the incognito one
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].urls, vec!["https://a.example", "https://b.example"]);
+        assert_eq!(reports[1].urls, vec!["https://p.example"]);
+        assert!(reports.iter().all(|r| !r.ok()));
+        assert!(failures(&reports).unwrap().starts_with("2 of 2 batches failed ( 3 tabs )"));
+        assert!(failures(&[BatchReport { urls : vec![] , error : None }]).is_none());
+    }
+}
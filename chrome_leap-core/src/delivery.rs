@@ -0,0 +1,507 @@
+//! tab transfers that wait for an answer
+//!
+//! sender ---- tabs { id } ----> receiver ---- [open] ----> ack { id , failed } / nack { id , reason } ----> sender
+//! no answer in time = the same line again , with a longer wait each try
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use chrome_leap_proto::{GlobalMsg, TabInfo, WindowInfo};
+
+use crate::auth::SEAL_OVERHEAD;
+use crate::browser::{self, BrowserLauncher};
+use crate::frame::MAX_FRAME;
+use crate::local_ws::{self, Extensions, TelOutcome};
+use crate::url_policy::UrlPolicy;
+
+/// how long to wait for an ack and how often to try
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retry {
+    pub timeout : Duration,
+    /// the wait doubles every try up to this
+    pub max_timeout : Duration,
+    /// sends in total , the first one included
+    pub attempts : u32,
+}
+
+impl Default for Retry {
+    fn default() -> Self {
+        Retry {
+            timeout : Duration::from_secs(5),
+            max_timeout : Duration::from_secs(30),
+            attempts : 4,
+        }
+    }
+}
+
+impl Retry {
+    /// wait after send number `attempt` ( 1 = first send )
+    pub fn wait(&self , attempt : u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.timeout.saturating_mul(factor).min(self.max_timeout)
+    }
+}
+
+/// tabs ids , unique per process and unlikely to repeat after a restart
+pub fn next_id() -> u64 {
+    static NEXT: OnceLock<AtomicU64> = OnceLock::new();
+    NEXT.get_or_init(|| {
+        let ms = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(1);
+        AtomicU64::new(ms << 16)
+    })
+    .fetch_add(1 , Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// already serialized , a retry sends the exact same line
+    pub line : String,
+    pub tabs : usize,
+    pub attempt : u32,
+    pub first_sent : Instant,
+    due : Instant,
+}
+
+/// what the sender has to do now
+#[derive(Debug, Clone, PartialEq)]
+pub enum Due {
+    Resend { id : u64 , line : String , attempt : u32 },
+    GaveUp { id : u64 , tabs : usize , attempts : u32 },
+}
+
+/// tabs sent on one link that haven't been answered yet
+#[derive(Debug, Default)]
+pub struct Pending {
+    retry : Retry,
+    entries : HashMap<u64 , Entry>,
+}
+
+impl Pending {
+    pub fn new(retry : Retry) -> Pending {
+        Pending { retry , entries : HashMap::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// call right after the first send of `line`
+    pub fn track(&mut self , id : u64 , line : String , tabs : usize , now : Instant) {
+        let due = now + self.retry.wait(1);
+        self.entries.insert(id , Entry { line , tabs , attempt : 1 , first_sent : now , due });
+    }
+
+    /// when the next timeout fires , None = nothing to wait for
+    pub fn next_due(&self) -> Option<Instant> {
+        self.entries.values().map(|e| e.due).min()
+    }
+
+    /// everything that timed out by `now` , resends are rescheduled , give ups are dropped
+    pub fn poll(&mut self , now : Instant) -> Vec<Due> {
+        let mut expired: Vec<u64> = self.entries.iter().filter(|(_ , e)| e.due <= now).map(|(id , _)| *id).collect();
+        expired.sort_unstable();
+
+        let mut out = Vec::new();
+        for id in expired {
+            let Some(entry) = self.entries.get_mut(&id) else {
+                continue;
+            };
+            if entry.attempt >= self.retry.attempts {
+                let entry = self.entries.remove(&id).expect("just looked it up");
+                out.push(Due::GaveUp { id , tabs : entry.tabs , attempts : entry.attempt });
+                continue;
+            }
+            entry.attempt += 1;
+            entry.due = now + self.retry.wait(entry.attempt);
+            out.push(Due::Resend { id , line : entry.line.clone() , attempt : entry.attempt });
+        }
+        out
+    }
+
+    /// forget the oldest throw , (id , tabs) , None = nothing tracked
+    pub fn drop_oldest(&mut self) -> Option<(u64 , usize)> {
+        let id = *self.entries.keys().min()?;
+        self.entries.remove(&id).map(|e| (id , e.tabs))
+    }
+
+    /// the link is gone , every line still waiting for an answer , oldest first
+    pub fn unanswered(&mut self) -> Vec<String> {
+        let mut left: Vec<(u64 , Entry)> = self.entries.drain().collect();
+        left.sort_unstable_by_key(|(id , _)| *id);
+        left.into_iter().map(|(_ , e)| e.line).collect()
+    }
+
+    /// ack / nack in , a line for the log out , None = not ours or already answered
+    pub fn answer(&mut self , msg : &GlobalMsg , now : Instant) -> Option<String> {
+        let (id , outcome) = match msg {
+            GlobalMsg::Ack { id , failed } => (*id , Ok(failed)),
+            GlobalMsg::Nack { id , reason } => (*id , Err(reason)),
+            GlobalMsg::Tabs { .. } | GlobalMsg::Ping | GlobalMsg::Pong => return None,
+        };
+        let entry = self.entries.remove(&id)?;
+        let took = now.saturating_duration_since(entry.first_sent).as_millis();
+        let tries = if entry.attempt == 1 { String::new() } else { format!(" , {} tries" , entry.attempt) };
+
+        Some(match outcome {
+            Ok(failed) if failed.is_empty() => format!("opened {} tabs ( id {} , {} ms{} )" , entry.tabs , id , took , tries),
+            Ok(failed) => format!(
+                "opened {} of {} tabs ( id {} , {} ms{} ) , failed : {}" ,
+                entry.tabs.saturating_sub(failed.len()) , entry.tabs , id , took , tries , failed.join(" ")
+            ),
+            Err(reason) => format!("could not open {} tabs ( id {} ) : {}" , entry.tabs , id , reason),
+        })
+    }
+}
+
+/// answers already given , a retry of something we opened gets the same answer instead of a second window
+#[derive(Debug)]
+pub struct Replies {
+    cap : usize,
+    order : VecDeque<u64>,
+    sent : HashMap<u64 , GlobalMsg>,
+}
+
+impl Default for Replies {
+    fn default() -> Self {
+        Replies::new(64)
+    }
+}
+
+impl Replies {
+    pub fn new(cap : usize) -> Replies {
+        Replies { cap : cap.max(1) , order : VecDeque::new() , sent : HashMap::new() }
+    }
+
+    pub fn get(&self , id : u64) -> Option<&GlobalMsg> {
+        self.sent.get(&id)
+    }
+
+    pub fn remember(&mut self , id : u64 , reply : GlobalMsg) {
+        if self.sent.insert(id , reply).is_none() {
+            self.order.push_back(id);
+        }
+        while self.order.len() > self.cap {
+            if let Some(old) = self.order.pop_front() {
+                self.sent.remove(&old);
+            }
+        }
+    }
+}
+
+/// `msg` as json that still fits one frame once sealed , check before sealing , a sealed line that isn't sent
+/// leaves a gap in the sequence numbers
+/// favicons go first when it doesn't fit ( they are often whole data: urls ) , Err = too big even without them
+pub fn frame_json(msg : &GlobalMsg) -> Result<String , String> {
+    let limit = MAX_FRAME - SEAL_OVERHEAD;
+    let json = serde_json::to_string(msg).map_err(|e| format!("can't serialize : {}" , e))?;
+    if json.len() <= limit {
+        return Ok(json);
+    }
+
+    let GlobalMsg::Tabs { id , tabs , time , window } = msg else {
+        return Err(format!("{} bytes is over the {} byte frame limit" , json.len() , limit));
+    };
+    let tabs: Vec<TabInfo> = tabs.iter().cloned().map(|tab| TabInfo { fav_icon_url : None , ..tab }).collect();
+    let count = tabs.len();
+    let slim = serde_json::to_string(&GlobalMsg::Tabs { id : *id , tabs , time : time.clone() , window : window.clone() })
+        .map_err(|e| format!("can't serialize : {}" , e))?;
+    if slim.len() > limit {
+        return Err(format!("{} tabs are {} bytes even without favicons , the frame limit is {}" , count , slim.len() , limit));
+    }
+    println!("[TCP] tabs {} are {} bytes , sending them without favicons ( {} bytes )" , id , json.len() , slim.len());
+    Ok(slim)
+}
+
+/// `Replies` per device id , shared by every link a device ever had , so a throw it sends again after a reconnect
+/// gets the first answer instead of a second window
+#[derive(Debug, Clone, Default)]
+pub struct PeerReplies {
+    peers : Arc<Mutex<HashMap<String , Arc<tokio::sync::Mutex<Replies>>>>>,
+}
+
+impl PeerReplies {
+    /// the answer to tabs `id` from `peer` , `open` only runs the first time the id shows up on any of its links
+    /// true = a repeat , answered from memory
+    pub async fn answer<F , Fut>(&self , peer : &str , id : u64 , open : F) -> (GlobalMsg , bool)
+    where
+        F : FnOnce() -> Fut,
+        Fut : Future<Output = GlobalMsg>,
+    {
+        let replies = self.peers.lock().unwrap_or_else(|e| e.into_inner()).entry(peer.to_string()).or_default().clone();
+        //held while opening , the same id on a newer link waits for this answer instead of opening again
+        let mut replies = replies.lock().await;
+        if let Some(reply) = replies.get(id) {
+            return (reply.clone() , true);
+        }
+        let reply = open().await;
+        replies.remember(id , reply.clone());
+        (reply , false)
+    }
+}
+
+/// receiving side : urls through `policy` , then one chrome_ext if one is registered , the launcher if not , and what to answer
+/// an ack from chrome_ext only comes once it said the tabs are open
+pub async fn open(
+    extensions : &Extensions,
+    browser : Option<&dyn BrowserLauncher>,
+    policy : &UrlPolicy,
+    id : u64,
+    tabs : Vec<TabInfo>,
+    window : Option<WindowInfo>,
+    ) -> GlobalMsg {
+
+    //refused urls go back in the ack like any other url that didn't open
+    let (tabs , rejected) = policy.filter(tabs);
+    for r in &rejected {
+        println!("[POLICY] not opening {}" , r);
+    }
+    let mut failed: Vec<String> = rejected.into_iter().map(|r| r.url).collect();
+    if tabs.is_empty() {
+        return GlobalMsg::Nack { id , reason : format!("all {} urls refused by the url policy" , failed.len()) };
+    }
+
+    //chrome_ext first , it keeps pins , groups and the window
+    let count = tabs.len();
+    match extensions.tel(tabs.clone() , window.clone()).await {
+        TelOutcome::Opened { failed : not_opened } => {
+            println!("[WS] the extension opened {} tabs" , count - not_opened.len().min(count));
+            failed.extend(not_opened);
+            return GlobalMsg::Ack { id , failed };
+        }
+        TelOutcome::Failed(reason) => {
+            println!("[WS] the extension couldn't open {} tabs : {}" , count , reason);
+            return GlobalMsg::Nack { id , reason : format!("the extension couldn't open them : {}" , reason) };
+        }
+        //it may still open them , the launcher would make a second window
+        TelOutcome::NoAnswer => {
+            println!("[WS] the extension didn't confirm {} tabs" , count);
+            return GlobalMsg::Nack { id , reason : format!("the extension didn't confirm within {}s" , local_ws::TEL_WAIT.as_secs()) };
+        }
+        TelOutcome::NoExtension => {}
+    }
+
+    let Some(browser) = browser else {
+        println!("[BROWSER] no browser , dropping {} tabs" , count);
+        return GlobalMsg::Nack { id , reason : "no extension connected and no browser found".to_string() };
+    };
+
+    let reports = browser.open(&tabs , window.as_ref());
+    match browser::failures(&reports) {
+        None => GlobalMsg::Ack { id , failed },
+        Some(reason) => {
+            println!("[BROWSER] {}" , reason);
+            if reports.iter().all(|r| !r.ok()) {
+                GlobalMsg::Nack { id , reason }
+            } else {
+                failed.extend(reports.into_iter().filter(|r| !r.ok()).flat_map(|r| r.urls));
+                GlobalMsg::Ack { id , failed }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retry() -> Retry {
+        Retry { timeout : Duration::from_secs(1) , max_timeout : Duration::from_secs(3) , attempts : 3 }
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let retry = retry();
+        let waits: Vec<u64> = (1..=4).map(|a| retry.wait(a).as_secs()).collect();
+        assert_eq!(waits, vec![1, 2, 3, 3]);
+        assert_eq!(retry.wait(80), Duration::from_secs(3));
+    }
+
+    #[test]
+    fn resends_then_gives_up() {
+        let start = Instant::now();
+        let mut pending = Pending::new(retry());
+        pending.track(1, "line".to_string(), 2, start);
+
+        assert!(pending.poll(start).is_empty());
+        assert_eq!(pending.next_due(), Some(start + Duration::from_secs(1)));
+
+        let at = start + Duration::from_secs(1);
+        assert_eq!(pending.poll(at), vec![Due::Resend { id : 1 , line : "line".to_string() , attempt : 2 }]);
+
+        let at = at + Duration::from_secs(2);
+        assert_eq!(pending.poll(at), vec![Due::Resend { id : 1 , line : "line".to_string() , attempt : 3 }]);
+
+        let at = at + Duration::from_secs(3);
+        assert_eq!(pending.poll(at), vec![Due::GaveUp { id : 1 , tabs : 2 , attempts : 3 }]);
+        assert!(pending.is_empty());
+        assert_eq!(pending.next_due(), None);
+    }
+
+    #[test]
+    fn answers_settle_once() {
+        let start = Instant::now();
+        let mut pending = Pending::new(retry());
+        pending.track(1, "a".to_string(), 3, start);
+        pending.track(2, "b".to_string(), 1, start);
+
+        let ack = GlobalMsg::Ack { id : 1 , failed : vec!["https://x.example".to_string()] };
+        let line = pending.answer(&ack, start + Duration::from_millis(40)).unwrap();
+        assert!(line.starts_with("opened 2 of 3 tabs"), "{}", line);
+        assert!(line.ends_with("failed : https://x.example"), "{}", line);
+        assert!(pending.answer(&ack, start).is_none());
+
+        let nack = GlobalMsg::Nack { id : 2 , reason : "no browser".to_string() };
+        assert!(pending.answer(&nack, start).unwrap().ends_with(": no browser"));
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn unanswered_in_send_order() {
+        let start = Instant::now();
+        let mut pending = Pending::new(retry());
+        pending.track(8, "b".to_string(), 1, start);
+        pending.track(3, "a".to_string(), 1, start);
+        assert_eq!(pending.unanswered(), vec!["a", "b"]);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn oldest_is_dropped_first() {
+        let now = Instant::now();
+        let mut pending = Pending::new(retry());
+        for id in [5, 3, 9] {
+            pending.track(id, format!("line {}", id), 1, now);
+        }
+        assert_eq!(pending.drop_oldest(), Some((3, 1)));
+        assert_eq!(pending.unanswered(), vec!["line 5".to_string(), "line 9".to_string()]);
+        assert_eq!(pending.drop_oldest(), None);
+    }
+
+    #[test]
+    fn replies_forget_the_oldest() {
+        let mut replies = Replies::new(2);
+        for id in 1..=3 {
+            replies.remember(id, GlobalMsg::Ack { id , failed : vec![] });
+        }
+        assert!(replies.get(1).is_none());
+        assert_eq!(replies.get(3), Some(&GlobalMsg::Ack { id : 3 , failed : vec![] }));
+    }
+
+    #[test]
+    fn oversized_throws_lose_their_favicons_or_are_refused() {
+        let icon = format!("data:image/png;base64,{}", "A".repeat(MAX_FRAME));
+        let tab = TabInfo { fav_icon_url : Some(icon) , ..TabInfo::from_url("https://a.example") };
+        let msg = GlobalMsg::Tabs { id : 1 , tabs : vec![tab] , time : "1".to_string() , window : None };
+        let json = frame_json(&msg).unwrap();
+        assert!(json.len() + SEAL_OVERHEAD <= MAX_FRAME);
+        assert!(!json.contains("data:image"));
+
+        let small = GlobalMsg::Tabs { id : 2 , tabs : vec![TabInfo::from_url("https://a.example")] , time : "1".to_string() , window : None };
+        assert_eq!(frame_json(&small).unwrap(), serde_json::to_string(&small).unwrap());
+
+        let long = format!("https://a.example/{}", "x".repeat(MAX_FRAME));
+        let huge = GlobalMsg::Tabs { id : 3 , tabs : vec![TabInfo::from_url(&long)] , time : "1".to_string() , window : None };
+        assert!(frame_json(&huge).unwrap_err().contains("even without favicons"));
+    }
+
+    #[tokio::test]
+    async fn a_repeat_on_a_new_link_is_not_opened_again() {
+        let replies = PeerReplies::default();
+        let opened = Arc::new(AtomicU64::new(0));
+        let open = |opened : Arc<AtomicU64>| move || async move {
+            opened.fetch_add(1, Ordering::SeqCst);
+            tokio::task::yield_now().await;
+            GlobalMsg::Ack { id : 7 , failed : vec![] }
+        };
+
+        //the old link is still opening when the client sends the same id on its new one
+        let (old_link , new_link) = (replies.clone(), replies.clone());
+        let first = tokio::spawn({ let open = open(opened.clone()); async move { old_link.answer("laptop", 7, open).await } });
+        let second = tokio::spawn({ let open = open(opened.clone()); async move { new_link.answer("laptop", 7, open).await } });
+        let (first , second) = (first.await.unwrap(), second.await.unwrap());
+        assert_eq!(opened.load(Ordering::SeqCst), 1);
+        assert_eq!(first.0, second.0);
+        assert!(first.1 != second.1);
+
+        //a link made later still knows , another device with the same id doesn't
+        assert!(replies.answer("laptop", 7, open(opened.clone())).await.1);
+        assert!(!replies.answer("desktop", 7, open(opened.clone())).await.1);
+        assert_eq!(opened.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn ids_do_not_repeat() {
+        let a = next_id();
+        assert!(next_id() > a);
+    }
+
+    use std::net::SocketAddr;
+
+    use tokio::sync::broadcast;
+
+    use crate::registry::{Extension, Registry};
+
+    //a registered extension that answers every tel with `answer`
+    fn extension(answer : Option<Result<Vec<String> , String>>) -> Extensions {
+        let (local_tx , _) = broadcast::channel::<local_ws::LocalEvent>(4);
+        let registry = Registry::default();
+        let addr = SocketAddr::from(([127, 0, 0, 1], 1));
+        registry.register(Extension { addr , browser : "chrome".to_string() , profile : "Default".to_string() , windows : vec![] });
+        let extensions = Extensions::new(local_tx.clone(), registry);
+
+        let mut local_recv = local_tx.subscribe();
+        let answering = extensions.clone();
+        tokio::spawn(async move {
+            while let Ok(local_ws::LocalEvent::Tel { id , .. }) = local_recv.recv().await {
+                if let Some(answer) = answer.clone() {
+                    answering.settle(id, addr, answer);
+                }
+            }
+        });
+        extensions
+    }
+
+    #[tokio::test]
+    async fn nothing_to_open_with_is_a_nack() {
+        let (local_tx , _) = broadcast::channel::<local_ws::LocalEvent>(4);
+        let nobody = Extensions::new(local_tx, Registry::default());
+        let policy = UrlPolicy::default();
+        let reply = open(&nobody, None, &policy, 9, vec![TabInfo::from_url("https://a.example")], None).await;
+        assert!(matches!(reply, GlobalMsg::Nack { id : 9 , .. }));
+
+        let extensions = extension(Some(Ok(vec![])));
+        assert_eq!(open(&extensions, None, &policy, 9, vec![TabInfo::from_url("https://a.example")], None).await, GlobalMsg::Ack { id : 9 , failed : vec![] });
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn ack_waits_for_the_extension() {
+        let policy = UrlPolicy::default();
+        let silent = extension(None);
+        let reply = open(&silent, None, &policy, 3, vec![TabInfo::from_url("https://a.example")], None).await;
+        assert!(matches!(reply, GlobalMsg::Nack { id : 3 , reason } if reason.contains("didn't confirm")));
+
+        let failing = extension(Some(Err("no window".to_string())));
+        let reply = open(&failing, None, &policy, 4, vec![TabInfo::from_url("https://a.example")], None).await;
+        assert!(matches!(reply, GlobalMsg::Nack { id : 4 , reason } if reason.contains("no window")));
+    }
+
+    #[tokio::test]
+    async fn refused_urls_are_reported() {
+        let extensions = extension(Some(Ok(vec!["https://b.example".to_string()])));
+        let policy = UrlPolicy::default();
+
+        let tabs = vec![TabInfo::from_url("https://a.example"), TabInfo::from_url("https://b.example"), TabInfo::from_url("--no-sandbox")];
+        let failed = vec!["--no-sandbox".to_string(), "https://b.example".to_string()];
+        assert_eq!(open(&extensions, None, &policy, 5, tabs, None).await, GlobalMsg::Ack { id : 5 , failed });
+
+        let reply = open(&extensions, None, &policy, 6, vec![TabInfo::from_url("file:///etc/passwd")], None).await;
+        assert!(matches!(reply, GlobalMsg::Nack { id : 6 , reason } if reason.contains("url policy")));
+    }
+}
@@ -0,0 +1,199 @@
+//! zero-config lan discovery over mdns / dns-sd
+//!
+//! server ---- [_chromeleap._tcp , txt : hostname transport fp version] ----> lan
+//! client ---- browse ----> every server that answered , pick one and connect
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::anyhow;
+use mdns_sd::{ServiceEvent, ServiceInfo};
+use tokio::time::{timeout_at, Instant};
+
+use chrome_leap_proto::PROTOCOL_VERSION;
+
+use crate::transport::Transport;
+
+pub use mdns_sd::ServiceDaemon;
+
+/// dns-sd service type the server advertises , the name says tcp but quic servers use it too
+pub const SERVICE_TYPE: &str = "_chromeleap._tcp.local.";
+
+/// one server found on the lan
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub instance: String,
+    pub hostname: String,
+    pub addrs: Vec<IpAddr>,
+    pub port: u16,
+    pub transport: Transport,
+    //only quic servers have a cert to pin
+    pub fingerprint: Option<String>,
+    pub version: Option<u32>,
+}
+
+impl Peer {
+    /// first address to dial , ipv4 before ipv6
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addrs
+            .iter()
+            .find(|ip| ip.is_ipv4())
+            .or_else(|| self.addrs.first())
+            .map(|ip| SocketAddr::new(*ip, self.port))
+    }
+
+    fn from_info(info : &ServiceInfo) -> Option<Peer> {
+        let hostname = info.get_property_val_str("hostname")?.to_string();
+        let transport = info
+            .get_property_val_str("transport")
+            .and_then(Transport::parse)
+            .unwrap_or_default();
+
+        let mut addrs: Vec<IpAddr> = info.get_addresses().iter().copied().collect();
+        addrs.sort();
+
+        Some(Peer {
+            instance: info.get_fullname().to_string(),
+            hostname,
+            addrs,
+            port: info.get_port(),
+            transport,
+            fingerprint: info.get_property_val_str("fp").filter(|fp| !fp.is_empty()).map(str::to_string),
+            version: info.get_property_val_str("version").and_then(|v| v.parse().ok()),
+        })
+    }
+}
+
+/// what the server puts on the lan , `addrs` empty = every address of every interface
+pub fn service_info(
+    hostname : &str,
+    port : u16,
+    transport : Transport,
+    fingerprint : Option<&str>,
+    addrs : &[IpAddr],
+) -> anyhow::Result<ServiceInfo> {
+    let version = PROTOCOL_VERSION.to_string();
+    let properties = [
+        ("hostname", hostname),
+        ("transport", transport.as_str()),
+        ("fp", fingerprint.unwrap_or("")),
+        ("version", version.as_str()),
+    ];
+
+    //mdns host names can't have spaces or dots
+    let label: String = hostname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' })
+        .collect();
+
+    let info = ServiceInfo::new(SERVICE_TYPE, hostname, &format!("{}.local.", label), addrs, port, &properties[..])
+        .map_err(|e| anyhow!("bad mdns service info : {}", e))?;
+
+    Ok(if addrs.is_empty() { info.enable_addr_auto() } else { info })
+}
+
+/// start answering mdns queries , keep the daemon alive for as long as we should be found
+pub fn advertise(
+    hostname : &str,
+    port : u16,
+    transport : Transport,
+    fingerprint : Option<&str>,
+) -> anyhow::Result<ServiceDaemon> {
+    let daemon = ServiceDaemon::new().map_err(|e| anyhow!("can't start mdns : {}", e))?;
+    daemon
+        .register(service_info(hostname, port, transport, fingerprint, &[])?)
+        .map_err(|e| anyhow!("can't advertise on mdns : {}", e))?;
+    Ok(daemon)
+}
+
+/// every server that answers within `wait` , sorted by hostname
+pub async fn browse(daemon : &ServiceDaemon , wait : Duration) -> anyhow::Result<Vec<Peer>> {
+    let events = daemon
+        .browse(SERVICE_TYPE)
+        .map_err(|e| anyhow!("can't browse mdns : {}", e))?;
+
+    let deadline = Instant::now() + wait;
+    let mut found: HashMap<String, Peer> = HashMap::new();
+
+    while let Ok(Ok(event)) = timeout_at(deadline, events.recv_async()).await {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                if let Some(peer) = Peer::from_info(&info) {
+                    found.insert(peer.instance.clone(), peer);
+                }
+            }
+            ServiceEvent::ServiceRemoved(_, fullname) => {
+                found.remove(&fullname);
+            }
+            _ => {}
+        }
+    }
+
+    let _ = daemon.stop_browse(SERVICE_TYPE);
+
+    let mut peers: Vec<Peer> = found.into_values().collect();
+    peers.sort_by(|a, b| a.hostname.cmp(&b.hostname));
+    Ok(peers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mdns_sd::IfKind;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn txt_record_round_trip() {
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 168, 1, 10));
+        let info = service_info("desk top.lan", 24811, Transport::Quic, Some("ab12"), &[ip]).unwrap();
+        let peer = Peer::from_info(&info).unwrap();
+
+        assert_eq!(peer.hostname, "desk top.lan");
+        assert_eq!(peer.transport, Transport::Quic);
+        assert_eq!(peer.fingerprint.as_deref(), Some("ab12"));
+        assert_eq!(peer.version, Some(PROTOCOL_VERSION));
+        assert_eq!(peer.addr(), Some(SocketAddr::new(ip, 24811)));
+    }
+
+    #[test]
+    fn tcp_server_has_no_fingerprint() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let info = service_info("desktop", 24811, Transport::Tcp, None, &[ip]).unwrap();
+        let peer = Peer::from_info(&info).unwrap();
+        assert_eq!(peer.transport, Transport::Tcp);
+        assert_eq!(peer.fingerprint, None);
+    }
+
+    #[test]
+    fn ipv4_is_dialed_first() {
+        let peer = Peer {
+            instance: "x".into(),
+            hostname: "x".into(),
+            addrs: vec!["::1".parse().unwrap(), "10.0.0.2".parse().unwrap()],
+            port: 1,
+            transport: Transport::Tcp,
+            fingerprint: None,
+            version: None,
+        };
+        assert_eq!(peer.addr(), Some("10.0.0.2:1".parse().unwrap()));
+    }
+
+    //in-process responder on loopback , nothing leaves the machine
+    #[tokio::test]
+    async fn browse_finds_loopback_server() {
+        let daemon = ServiceDaemon::new().unwrap();
+        daemon.enable_interface(IfKind::LoopbackV4).unwrap();
+
+        let hostname = format!("test-{}", std::process::id());
+        let info = service_info(&hostname, 24811, Transport::Quic, Some("ab12"), &[IpAddr::V4(Ipv4Addr::LOCALHOST)]).unwrap();
+        daemon.register(info).unwrap();
+
+        let peers = browse(&daemon, Duration::from_secs(2)).await.unwrap();
+        let _ = daemon.shutdown();
+
+        let peer = peers.iter().find(|p| p.hostname == hostname).expect("server was not discovered");
+        assert_eq!(peer.fingerprint.as_deref(), Some("ab12"));
+        assert_eq!(peer.addr(), Some("127.0.0.1:24811".parse().unwrap()));
+    }
+}
@@ -0,0 +1,204 @@
+//! length prefixed frames , everything after the handshake / auth lines travels in these
+//!
+//! [ u32 big endian length ][ sealed line , utf-8 ]
+//! one read can stop in the middle of a frame or carry several , the decoder keeps the rest for the next call
+
+use anyhow::{anyhow, bail};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/// biggest frame either side sends or accepts , a few hundred tabs are well under this
+pub const MAX_FRAME: usize = 1024 * 1024;
+
+const HEADER: usize = 4;
+
+/// length prefix + payload , Err if it's over `MAX_FRAME`
+pub fn encode(payload : &str) -> anyhow::Result<Vec<u8>> {
+    let len = payload.len();
+    if len > MAX_FRAME {
+        bail!("frame of {} bytes is over the {} byte limit" , len , MAX_FRAME);
+    }
+    let mut out = Vec::with_capacity(HEADER + len);
+    out.extend_from_slice(&(len as u32).to_be_bytes());
+    out.extend_from_slice(payload.as_bytes());
+    Ok(out)
+}
+
+/// bytes in whatever pieces the socket hands them over , whole frames out
+#[derive(Debug)]
+pub struct Decoder {
+    buf : Vec<u8>,
+    max : usize,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new(MAX_FRAME)
+    }
+}
+
+impl Decoder {
+    pub fn new(max : usize) -> Decoder {
+        Decoder { buf : Vec::new() , max }
+    }
+
+    pub fn extend(&mut self , bytes : &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// bytes waiting for the rest of their frame
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Ok(None) = need more bytes , Err = the peer sent garbage and the link can't be trusted anymore
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<String>> {
+        let Some(header) = self.buf.first_chunk::<HEADER>() else {
+            return Ok(None);
+        };
+        let len = u32::from_be_bytes(*header) as usize;
+
+        //checked before the body shows up so a bad length can't make us buffer a gigabyte
+        if len > self.max {
+            bail!("peer announced a {} byte frame , limit is {}" , len , self.max);
+        }
+        if self.buf.len() < HEADER + len {
+            return Ok(None);
+        }
+
+        let body: Vec<u8> = self.buf.drain(..HEADER + len).skip(HEADER).collect();
+        String::from_utf8(body).map(Some).map_err(|e| anyhow!("frame is not utf-8 : {}" , e))
+    }
+}
+
+/// read half of a link , one frame per call
+pub struct FrameReader<R> {
+    inner : R,
+    decoder : Decoder,
+    chunk : Vec<u8>,
+}
+
+impl<R : AsyncRead + Unpin> FrameReader<R> {
+    pub fn new(inner : R) -> FrameReader<R> {
+        FrameReader { inner , decoder : Decoder::default() , chunk : vec![0; 8 * 1024] }
+    }
+
+    /// Ok(None) = the peer closed between two frames
+    /// cancel safe , bytes only move into the decoder after a read finished so a select! can drop this freely
+    pub async fn next_frame(&mut self) -> anyhow::Result<Option<String>> {
+        loop {
+            if let Some(frame) = self.decoder.next_frame()? {
+                return Ok(Some(frame));
+            }
+
+            let n = self.inner.read(&mut self.chunk).await?;
+            if n == 0 {
+                if self.decoder.buffered() == 0 {
+                    return Ok(None);
+                }
+                bail!("peer closed in the middle of a frame ( {} bytes left over )" , self.decoder.buffered());
+            }
+            self.decoder.extend(&self.chunk[..n]);
+        }
+    }
+}
+
+/// one frame out , the whole thing in a single write so frames never interleave
+pub async fn write_frame<W : AsyncWrite + Unpin>(writer : &mut W , payload : &str) -> anyhow::Result<()> {
+    writer.write_all(&encode(payload)?).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(frames : &[&str]) -> Vec<u8> {
+        frames.iter().flat_map(|f| encode(f).unwrap()).collect()
+    }
+
+    #[test]
+    fn fragmented_bytes_make_whole_frames() {
+        let bytes = stream(&["1 aa {\"action\":\"ping\"}", "2 bb {\"action\":\"pong\"}"]);
+        let mut decoder = Decoder::default();
+        let mut out = Vec::new();
+        for byte in bytes {
+            decoder.extend(&[byte]);
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                out.push(frame);
+            }
+        }
+        assert_eq!(out, vec!["1 aa {\"action\":\"ping\"}", "2 bb {\"action\":\"pong\"}"]);
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn coalesced_frames_come_out_one_by_one() {
+        let mut bytes = stream(&["a", "", "line with\nnewline"]);
+        let tail = encode("next").unwrap();
+        bytes.extend_from_slice(&tail[..3]);
+
+        let mut decoder = Decoder::default();
+        decoder.extend(&bytes);
+        assert_eq!(decoder.next_frame().unwrap().as_deref(), Some("a"));
+        assert_eq!(decoder.next_frame().unwrap().as_deref(), Some(""));
+        assert_eq!(decoder.next_frame().unwrap().as_deref(), Some("line with\nnewline"));
+        assert_eq!(decoder.next_frame().unwrap(), None);
+
+        decoder.extend(&tail[3..]);
+        assert_eq!(decoder.next_frame().unwrap().as_deref(), Some("next"));
+    }
+
+    #[test]
+    fn oversized_frames_are_refused() {
+        assert!(encode(&"x".repeat(MAX_FRAME + 1)).is_err());
+
+        //only the header has arrived , that's already enough to refuse it
+        let mut decoder = Decoder::new(16);
+        decoder.extend(&17u32.to_be_bytes());
+        assert!(decoder.next_frame().unwrap_err().to_string().contains("17 byte frame"));
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let mut decoder = Decoder::default();
+        decoder.extend(&[0, 0, 0, 2, 0xff, 0xfe]);
+        assert!(decoder.next_frame().is_err());
+    }
+
+    #[tokio::test]
+    async fn reader_survives_split_writes() {
+        let (mut tx , rx) = tokio::io::duplex(64);
+        let bytes = stream(&["first", &"y".repeat(300), "third"]);
+        tokio::spawn(async move {
+            //odd sizes so the frame boundaries never line up with the writes
+            for piece in bytes.chunks(7) {
+                tx.write_all(piece).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        });
+
+        let mut reader = FrameReader::new(rx);
+        assert_eq!(reader.next_frame().await.unwrap().as_deref(), Some("first"));
+        assert_eq!(reader.next_frame().await.unwrap().map(|f| f.len()), Some(300));
+        assert_eq!(reader.next_frame().await.unwrap().as_deref(), Some("third"));
+        assert_eq!(reader.next_frame().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn eof_mid_frame_is_an_error() {
+        let (mut tx , rx) = tokio::io::duplex(64);
+        tx.write_all(&encode("cut short").unwrap()[..6]).await.unwrap();
+        drop(tx);
+        assert!(FrameReader::new(rx).next_frame().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_frame_round_trip() {
+        let (mut tx , rx) = tokio::io::duplex(64);
+        write_frame(&mut tx, "hello").await.unwrap();
+        drop(tx);
+        let mut reader = FrameReader::new(rx);
+        assert_eq!(reader.next_frame().await.unwrap().as_deref(), Some("hello"));
+        assert_eq!(reader.next_frame().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,17 @@
+//! runtime pieces every chrome_leap machine runs , whichever side of the tcp link it is on
+//!
+//! edge checker ----- local_channel -----> local_ws ( forwarder ) --- ws ---> chrome_ext
+//! another_computer ---- tcp / quic ( transport ) ----> browser ( launcher )
+
+pub mod auth;
+pub mod browser;
+pub mod delivery;
+pub mod discovery;
+pub mod frame;
+pub mod local_ws;
+pub mod log;
+pub mod os_check;
+pub mod registry;
+pub mod tab_requests;
+pub mod transport;
+pub mod url_policy;
@@ -0,0 +1,542 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use futures_util::{StreamExt, SinkExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tokio_tungstenite::tungstenite::handshake::server::{Callback, ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::http::StatusCode;
+use tokio_tungstenite::{accept_hdr_async, tungstenite::protocol::Message, WebSocketStream};
+
+use chrome_leap_proto::{ClientMsg, ServerMsg, TabInfo, WindowInfo};
+
+use crate::os_check::EdgeEvent;
+use crate::registry::{Extension, Registry};
+use crate::tab_requests::{self, Requests, REPLY_WAIT};
+use crate::url_policy::UrlPolicy;
+
+/// what travels on local_channel , every connected chrome_ext gets its own copy
+#[derive(Debug, Clone, PartialEq)]
+pub enum LocalEvent {
+    /// edge checker ----> chrome_ext , send your tabs , answered with `id`
+    /// `to` = the extension that owns the front window , None = ask every extension
+    Edge { id : u64 , event : EdgeEvent , to : Option<SocketAddr> },
+    /// another_computer ----> chrome_ext , open these , answered with `opened` and `id`
+    /// only `to` opens them , the others would open the same tabs again
+    Tel { id : u64 , tabs : Vec<TabInfo> , window : Option<WindowInfo> , to : SocketAddr },
+}
+
+impl LocalEvent {
+    /// what chrome_ext gets over the ws
+    pub fn to_server_msg(&self) -> ServerMsg {
+        match self {
+            LocalEvent::Edge { id , event , .. } => ServerMsg::GetTabs { id : *id , edge : event.hit.to_token() },
+            LocalEvent::Tel { id , tabs , window , .. } => ServerMsg::Tel { id : *id , tabs : tabs.clone() , window : window.clone() },
+        }
+    }
+}
+
+/// how long a tel waits for chrome_ext to say the tabs are open , under the sender's 5s retry
+pub const TEL_WAIT : Duration = Duration::from_secs(3);
+
+/// how handing tabs to chrome_ext went
+#[derive(Debug, Clone, PartialEq)]
+pub enum TelOutcome {
+    /// it opened them , apart from these urls
+    Opened { failed : Vec<String> },
+    /// it tried and said why it couldn't
+    Failed(String),
+    /// nobody registered , or the chosen one left before answering , the launcher can have them
+    NoExtension,
+    /// sent but not confirmed within TEL_WAIT , it may still open them
+    NoAnswer,
+}
+
+type Waiting = HashMap<u64 , (SocketAddr , oneshot::Sender<Result<Vec<String> , String>>)>;
+
+/// the connected chrome_ext's as the rest of the program sees them : the local_channel ,
+/// who registered , get_tabs still out and tels waiting for their `opened` , cheap to clone
+#[derive(Clone)]
+pub struct Extensions {
+    local_tx : broadcast::Sender<LocalEvent>,
+    registry : Registry,
+    requests : Arc<Mutex<Requests>>,
+    waiting : Arc<Mutex<Waiting>>,
+}
+
+impl Extensions {
+    pub fn new(local_tx : broadcast::Sender<LocalEvent> , registry : Registry) -> Extensions {
+        Extensions { local_tx , registry , requests : Arc::default() , waiting : Arc::default() }
+    }
+
+    /// edge checker ----> chrome_ext , a get_tabs for this edge hit , registered here once so only its answers go on
+    /// only the extension whose window is in front is asked if the registry knows it , else all of them
+    pub fn get_tabs(&self , event : EdgeEvent) {
+        let to = self.registry.owner(&event.window);
+        let id = tab_requests::next_id();
+        let asked = if to.is_some() { 1 } else { self.local_tx.receiver_count() };
+        self.requests().ask(id , asked);
+        //no extension connected , nobody to answer
+        if self.local_tx.send(LocalEvent::Edge { id , event , to }).is_err() {
+            self.requests().cancel(id);
+        }
+    }
+
+    /// another_computer ---- tabs ----> [local_channel] ---- ws ----> one chrome_ext ( tel ) , then wait for its `opened`
+    pub async fn tel(&self , tabs : Vec<TabInfo> , window : Option<WindowInfo>) -> TelOutcome {
+        let Some(to) = self.registry.opener() else {
+            return TelOutcome::NoExtension;
+        };
+        let id = tab_requests::next_id();
+        let (done_tx , done_rx) = oneshot::channel();
+        self.waiting().insert(id , (to , done_tx));
+
+        //it may have unregistered since opener() , forget() has run for it then and won't see this id
+        if self.registry.get(to).is_none() || self.local_tx.send(LocalEvent::Tel { id , tabs , window , to }).is_err() {
+            self.waiting().remove(&id);
+            return TelOutcome::NoExtension;
+        }
+
+        match tokio::time::timeout(TEL_WAIT , done_rx).await {
+            Ok(Ok(Ok(failed))) => TelOutcome::Opened { failed },
+            Ok(Ok(Err(reason))) => TelOutcome::Failed(reason),
+            //forget() dropped the sender , the ws closed before it answered
+            Ok(Err(_)) => TelOutcome::NoExtension,
+            Err(_) => {
+                self.waiting().remove(&id);
+                TelOutcome::NoAnswer
+            }
+        }
+    }
+
+    /// chrome_ext at `from` answered tel `id` , only the one it was sent to counts
+    pub fn settle(&self , id : u64 , from : SocketAddr , outcome : Result<Vec<String> , String>) {
+        let mut waiting = self.waiting();
+        if waiting.get(&id).is_some_and(|(to , _)| *to == from) {
+            if let Some((_ , done_tx)) = waiting.remove(&id) {
+                let _ = done_tx.send(outcome);
+            }
+        }
+    }
+
+    /// `addr` closed , its unanswered tels go to the launcher
+    pub fn forget(&self , addr : SocketAddr) {
+        self.waiting().retain(|_ , (to , _)| *to != addr);
+    }
+
+    fn waiting(&self) -> MutexGuard<'_ , Waiting> {
+        self.waiting.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn requests(&self) -> MutexGuard<'_ , Requests> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// who may open the ws , checked on the http upgrade before any message is read
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WsGuard {
+    /// chrome extension ids allowed in the Origin header , empty = nothing may connect
+    /// ( any other extension the user installed could otherwise throw tabs )
+    pub extension_ids : Vec<String>,
+    /// the extension has to connect with ?token=<this>
+    pub token : Option<String>,
+}
+
+impl WsGuard {
+    /// Err(reason) = refuse the upgrade
+    /// a web page can open ws://127.0.0.1 too , its Origin is http(s) so it never gets past this
+    pub fn check(&self , origin : Option<&str> , query : Option<&str>) -> Result<() , String> {
+        let Some(origin) = origin else {
+            return Err("no Origin header".to_string());
+        };
+        let Some(id) = origin.strip_prefix("chrome-extension://") else {
+            return Err(format!("origin '{}' is not an extension" , origin));
+        };
+        let id = id.trim_end_matches('/');
+        if self.extension_ids.is_empty() {
+            return Err(format!("no extension ids are allowed , add '{}' to extension_ids / EXTENSION_IDS if it's ours" , id));
+        }
+        if !self.extension_ids.iter().any(|allowed| allowed == id) {
+            return Err(format!("extension '{}' is not in the allowlist" , id));
+        }
+
+        if let Some(token) = &self.token {
+            //the extension sends it through encodeURIComponent
+            let given = url::form_urlencoded::parse(query.unwrap_or("").as_bytes())
+                .find(|(key , _)| key == "token")
+                .map(|(_ , value)| value);
+            if !given.is_some_and(|given| same_bytes(given.as_bytes() , token.as_bytes())) {
+                return Err("missing or wrong token".to_string());
+            }
+        }
+        Ok(())
+    }
+}
+
+//no early return on the first differing byte , so the time taken doesn't tell how much of the token was right
+fn same_bytes(a : &[u8] , b : &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8 , |diff , (x , y)| diff | (x ^ y)) == 0
+}
+
+//Err = the 403 to answer with , boxed , a whole http response is big to pass around
+fn upgrade(request : &Request , peer_addr : SocketAddr , guard : &WsGuard) -> Result<() , Box<ErrorResponse>> {
+    let origin = request.headers().get("origin").and_then(|o| o.to_str().ok());
+    guard.check(origin , request.uri().query()).map_err(|reason| {
+        println!("[WS] refusing {} : {}" , peer_addr , reason);
+        let mut refuse = Box::new(ErrorResponse::new(Some(reason)));
+        *refuse.status_mut() = StatusCode::FORBIDDEN;
+        refuse
+    })
+}
+
+//tungstenite's side of the upgrade , its signature wants the response unboxed
+struct Check<'a> {
+    peer_addr : SocketAddr,
+    guard : &'a WsGuard,
+}
+
+impl Callback for Check<'_> {
+    fn on_request(self , request : &Request , response : Response) -> Result<Response , ErrorResponse> {
+        match upgrade(request , self.peer_addr , self.guard) {
+            Ok(()) => Ok(response),
+            Err(refuse) => Err(*refuse),
+        }
+    }
+}
+
+//http upgrade + WsGuard , None = refused or broken , already logged
+async fn accept(stream : TcpStream , peer_addr : SocketAddr , guard : &WsGuard) -> Option<WebSocketStream<TcpStream>> {
+    match accept_hdr_async(stream , Check { peer_addr , guard }).await {
+        Ok(ws) => Some(ws),
+        Err(e) => {
+            println!("[WS][handle_ws] handshake err : {} (from {})" , e , peer_addr);
+            None
+        }
+    }
+}
+
+//what every extension connection shares , one per listen()
+struct WsContext {
+    extensions : Extensions,
+    tabs_tx : mpsc::Sender<ClientMsg>,
+    guard : WsGuard,
+    policy : UrlPolicy,
+}
+
+//websocket listner
+//edge checker ----- local_channel -----> [forwarder] --- ws ---> chrome_ext
+//chrome_ext ---- ws ----> [forwarder] ---- tabs_tx ----> whoever owns the tcp link
+//extensions register into the registry , a get_tabs goes to the owner of the front window or to all of them ,
+//`requests` keeps the answers apart and lets one through , a tel goes to one of them
+pub async fn listen(
+    addr : SocketAddr,
+    extensions : Extensions,
+    tabs_tx : mpsc::Sender<ClientMsg>,
+    guard : WsGuard,
+    policy : UrlPolicy,
+    ) {
+
+    let listener: TcpListener = TcpListener::bind(addr).await.expect("[WS] can't bind with this addr");
+
+    println!("[WS] listening @ {}" , addr);
+    if guard.extension_ids.is_empty() {
+        println!("[WS] no extension ids are allowed , every extension will be refused until extension_ids / EXTENSION_IDS is set");
+    }
+    if !addr.ip().is_loopback() && guard.token.is_none() {
+        println!("[WS] {} is reachable from the lan and has no token , anything that fakes an extension Origin can throw tabs" , addr);
+    }
+    let context = Arc::new(WsContext { extensions , tabs_tx , guard , policy });
+
+    while let Ok((stream , peer_addr)) = listener.accept().await {
+        println!("[WS] accept conn from {}" , peer_addr);
+        tokio::spawn(handle_ws(stream , peer_addr , context.clone()));
+    }
+}
+
+async fn handle_ws(stream : TcpStream , peer_addr : SocketAddr , context : Arc<WsContext>) {
+
+    let Some(ws_stream) = accept(stream , peer_addr , &context.guard).await else {
+        return;
+    };
+    let (mut ws_sender , mut ws_reciver) = ws_stream.split();
+    let Extensions { local_tx , registry , .. } = &context.extensions;
+    let mut local_recv = local_tx.subscribe();
+
+    loop {
+        tokio::select! {
+
+            //edge checker / another_computer ----- [local_channel] -----> forwarder --- ws ---> chrome_ext
+            Ok(event) = local_recv.recv() => {
+                match &event {
+                    LocalEvent::Edge { id , event : edge , to } => {
+                        //meant for the extension that owns the front window , and that's not us
+                        if to.is_some_and(|to| to != peer_addr) {
+                            continue;
+                        }
+
+                        crate::debug!(
+                            "[WS] send get_tabs {} to {} ( {} @ {:.0},{:.0} on {}x{} , '{}' )" ,
+                            id , peer_addr , edge.hit.to_token() , edge.cursor.0 , edge.cursor.1 , edge.monitor.width , edge.monitor.height , edge.window.title
+                        );
+
+                        //first extension task to see it starts the clock , the others only answer
+                        if context.extensions.requests().start_clock(*id) {
+                            tokio::spawn(expire(*id , context.clone()));
+                        }
+                    }
+                    LocalEvent::Tel { id , tabs , to , .. } => {
+                        if *to != peer_addr {
+                            continue;
+                        }
+                        crate::debug!("[WS] send tel {} with {} tabs to {}" , id , tabs.len() , peer_addr);
+                    }
+                }
+
+                let json_msg = match serde_json::to_string(&event.to_server_msg()) {
+                    Ok(json_msg) => json_msg,
+                    Err(e) => {
+                        eprintln!("[serde_json] fail to convert {:?} to json , err : {}" , event , e);
+                        continue;
+                    }
+                };
+
+                //forwarder --- [ws] ---> chrome_ext
+                if let Err(e) = ws_sender.send(Message::Text(json_msg)).await {
+                    eprintln!("[WS] fail to send msg to {} , err : {}" , peer_addr , e);
+                    break; //ws prob disconnect so we break
+                }
+            }
+
+            //recv chrome_ext ---- [ws] ----> forwarder ----- tabs_tx ---- tcp ----> another_computer
+            msg = ws_reciver.next() => {
+
+                //stream over without a close frame , unregister so tels stop picking us
+                let Some(msg) = msg else {
+                    println!("[WS] {} disconnected.", peer_addr);
+                    break;
+                };
+
+                crate::debug!("[WS] forwarding from perr {}" , peer_addr);
+                let msg = match msg {
+                    Ok(msg) => msg,
+                    Err(e) => {
+                        println!("[WS] Error reciving msg err : {} ,  from {} " , e , peer_addr);
+                        break;
+                    }
+                };
+
+                //recv chrome_ext ---- ws ----> [forwarder] ---- tabs_tx ----- tcp ----> another_computer
+                if let Message::Text(text) = msg {
+                    match serde_json::from_str::<ClientMsg>(&text) {
+                        //who this is , sent again every time its windows change
+                        Ok(ClientMsg::Register { browser , profile , windows }) => {
+                            let count = windows.len();
+                            let new = registry.register(Extension { addr : peer_addr , browser : browser.clone() , profile : profile.clone() , windows });
+                            if new {
+                                println!("[WS] {} is {} profile '{}' ( {} windows )" , peer_addr , browser , profile , count);
+                            }
+                        }
+                        //answer to a tel
+                        Ok(ClientMsg::Opened { id , failed , error }) => {
+                            context.extensions.settle(id , peer_addr , error.map_or(Ok(failed) , Err));
+                        }
+                        Ok(client_msg) => {
+                            //only the settling answer goes on , the others were for the same edge hit
+                            let settled = context.extensions.requests().reply(client_msg);
+                            let Some(client_msg) = settled.and_then(|msg| allowed(msg , &context.policy)) else {
+                                continue;
+                            };
+                            if let Err(e) = context.tabs_tx.send(client_msg).await {
+                                println!("[WS] nobody is listening for tabs anymore : {}" , e);
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            println!("[serde json] Failed to parse JSON from {}: {}", peer_addr, e);
+                            println!("[serde json] Raw message was: {}", text);
+                        }
+                    }
+                } else if msg.is_close() {
+                    println!("[WS] {} disconnected.", peer_addr);
+                    break;
+                }
+            }
+
+        }
+    }
+
+    if let Some(ext) = registry.remove(peer_addr) {
+        println!("[WS] {} profile '{}' unregistered" , ext.browser , ext.profile);
+    }
+    context.extensions.forget(peer_addr);
+}
+
+//urls the policy refuses never leave this machine , None = nothing left to send
+fn allowed(msg : ClientMsg , policy : &UrlPolicy) -> Option<ClientMsg> {
+    let ClientMsg::Tabs { id , focused , tabs , edge , window } = msg else {
+        return Some(msg);
+    };
+    let (tabs , rejected) = policy.filter(tabs);
+    for r in &rejected {
+        println!("[POLICY] not sending {}" , r);
+    }
+    if tabs.is_empty() {
+        println!("[POLICY] nothing left to send from {} edge" , edge);
+        return None;
+    }
+    Some(ClientMsg::Tabs { id , focused , tabs , edge , window })
+}
+
+//not everyone answered in time , go with what came back
+async fn expire(id : u64 , context : Arc<WsContext>) {
+    tokio::time::sleep(REPLY_WAIT).await;
+    let settled = context.extensions.requests().expire(id);
+    if let Some(client_msg) = settled.and_then(|msg| allowed(msg , &context.policy)) {
+        let _ = context.tabs_tx.send(client_msg).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::SystemTime;
+
+    use crate::os_check::{ActiveWindow, Edge, EdgeHit, Monitor};
+
+    fn registered(extensions : &Extensions , port : u16) -> SocketAddr {
+        let addr = SocketAddr::from(([127, 0, 0, 1], port));
+        extensions.registry.register(Extension { addr , browser : "chrome".to_string() , profile : "Default".to_string() , windows : vec![] });
+        addr
+    }
+
+    #[tokio::test]
+    async fn tel_goes_to_one_extension_and_waits_for_it() {
+        let (local_tx , _) = broadcast::channel::<LocalEvent>(4);
+        let extensions = Extensions::new(local_tx.clone(), Registry::default());
+        assert_eq!(extensions.tel(vec![TabInfo::from_url("https://a-b.example")], None).await, TelOutcome::NoExtension);
+
+        let first = registered(&extensions, 1);
+        registered(&extensions, 2);
+        let mut extension = local_tx.subscribe();
+        let answering = extensions.clone();
+        tokio::spawn(async move {
+            let LocalEvent::Tel { id , to , .. } = extension.recv().await.unwrap() else {
+                panic!("expected tel");
+            };
+            assert_eq!(to, first);
+            //someone else answering doesn't count
+            answering.settle(id, SocketAddr::from(([127, 0, 0, 1], 2)), Ok(vec![]));
+            answering.settle(id, first, Ok(vec!["https://c.example".to_string()]));
+        });
+
+        let outcome = extensions.tel(vec![TabInfo::from_url("https://a-b.example")], None).await;
+        assert_eq!(outcome, TelOutcome::Opened { failed : vec!["https://c.example".to_string()] });
+    }
+
+    #[tokio::test]
+    async fn tel_to_a_closing_extension_falls_back() {
+        let (local_tx , _) = broadcast::channel::<LocalEvent>(4);
+        let extensions = Extensions::new(local_tx.clone(), Registry::default());
+        let addr = registered(&extensions, 1);
+        let mut extension = local_tx.subscribe();
+        let closing = extensions.clone();
+        tokio::spawn(async move {
+            extension.recv().await.unwrap();
+            closing.registry.remove(addr);
+            closing.forget(addr);
+        });
+        assert_eq!(extensions.tel(vec![TabInfo::from_url("https://a-b.example")], None).await, TelOutcome::NoExtension);
+    }
+
+    #[test]
+    fn guard_wants_an_extension_origin() {
+        let listed = WsGuard { extension_ids : vec!["abc".to_string()] , token : None };
+        assert!(listed.check(Some("chrome-extension://abc/"), None).is_ok());
+        assert!(listed.check(Some("chrome-extension://xyz"), None).unwrap_err().contains("allowlist"));
+        assert!(listed.check(Some("https://evil.example"), None).is_err());
+        assert!(listed.check(None, None).is_err());
+    }
+
+    #[test]
+    fn guard_needs_an_allowlist() {
+        let empty = WsGuard::default();
+        assert!(empty.check(Some("chrome-extension://abc"), None).unwrap_err().contains("EXTENSION_IDS"));
+    }
+
+    #[test]
+    fn guard_token() {
+        let guard = WsGuard { extension_ids : vec!["abc".to_string()] , token : Some("s3 cr&t=".to_string()) };
+        assert!(guard.check(Some("chrome-extension://abc"), Some("v=1&token=s3%20cr%26t%3D")).is_ok());
+        assert!(guard.check(Some("chrome-extension://abc"), Some("token=s3 cr&t=")).is_err());
+        assert!(guard.check(Some("chrome-extension://abc"), Some("token=s3%20cr%26t")).is_err());
+        assert!(guard.check(Some("chrome-extension://abc"), None).is_err());
+        assert!(!same_bytes(b"s3cret", b"s3creT"));
+        assert!(same_bytes(b"", b""));
+    }
+
+    #[tokio::test]
+    async fn upgrade_is_refused_without_the_right_origin() {
+        use tokio_tungstenite::client_async;
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let guard = WsGuard { extension_ids : vec!["abc".to_string()] , token : None };
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                let (stream , peer_addr) = listener.accept().await.unwrap();
+                let _ws = accept(stream, peer_addr, &guard).await;
+            }
+        });
+
+        for (origin , ok) in [("https://evil.example" , false) , ("chrome-extension://abc" , true)] {
+            let mut request = format!("ws://{}/", addr).into_client_request().unwrap();
+            request.headers_mut().insert("origin", origin.parse().unwrap());
+            let stream = TcpStream::connect(addr).await.unwrap();
+            assert_eq!(client_async(request, stream).await.is_ok(), ok, "{}", origin);
+        }
+    }
+
+    #[test]
+    fn get_tabs_is_registered_once_where_it_is_sent() {
+        let (local_tx , _) = broadcast::channel::<LocalEvent>(4);
+        let extensions = Extensions::new(local_tx.clone(), Registry::default());
+        let event = EdgeEvent {
+            hit : EdgeHit { edge : Edge::Right , at : 0.5 },
+            cursor : (1919.0, 500.0),
+            monitor : Monitor { width : 1920 , height : 1080 },
+            time : SystemTime::now(),
+            window : ActiveWindow { title : "Inbox - Google Chrome".to_string() , app : "Google Chrome".to_string() },
+        };
+
+        //nobody connected , nothing left waiting
+        extensions.get_tabs(event.clone());
+        assert!(extensions.requests().is_empty());
+
+        let (mut first , mut second) = (local_tx.subscribe(), local_tx.subscribe());
+        extensions.get_tabs(event);
+        let LocalEvent::Edge { id , .. } = first.try_recv().unwrap() else {
+            panic!("expected get_tabs");
+        };
+        assert!(matches!(second.try_recv().unwrap(), LocalEvent::Edge { id : same , .. } if same == id));
+        assert_eq!(extensions.requests().len(), 1);
+        assert!(extensions.requests().start_clock(id));
+        assert!(!extensions.requests().start_clock(id));
+    }
+
+    #[test]
+    fn edge_event_asks_for_tabs() {
+        let event = LocalEvent::Edge { id : 4 , to : None , event : EdgeEvent {
+            hit : EdgeHit { edge : Edge::TopRight , at : 0.0 },
+            cursor : (1919.0, 3.0),
+            monitor : Monitor { width : 1920 , height : 1080 },
+            time : SystemTime::now(),
+            window : ActiveWindow { title : "Inbox - Google Chrome".to_string() , app : "Google Chrome".to_string() },
+        }};
+        assert_eq!(event.to_server_msg(), ServerMsg::GetTabs { id : 4 , edge : "top_right@0.000".to_string() });
+    }
+}
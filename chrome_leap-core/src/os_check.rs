@@ -0,0 +1,359 @@
+use rdev::{listen, Event, EventType, Button, display_size};
+use std::{sync::{
+    Arc, 
+    atomic::{AtomicBool, Ordering}
+}, time::Duration};
+use std::time::{Instant, SystemTime};
+
+//Send save to send to another thread
+//Sync save to share between thread
+//static lifetime must remain constant ( life ) during program
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Edge {
+    pub const ALL : [Edge; 8] = [
+        Edge::Left, Edge::Right, Edge::Top, Edge::Bottom,
+        Edge::TopLeft, Edge::TopRight, Edge::BottomLeft, Edge::BottomRight,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Edge::Left => "left",
+            Edge::Right => "right",
+            Edge::Top => "top",
+            Edge::Bottom => "bottom",
+            Edge::TopLeft => "top_left",
+            Edge::TopRight => "top_right",
+            Edge::BottomLeft => "bottom_left",
+            Edge::BottomRight => "bottom_right",
+        }
+    }
+
+    pub fn parse(s : &str) -> Option<Edge> {
+        Edge::ALL.into_iter().find(|e| e.as_str() == s)
+    }
+
+    pub fn is_corner(&self) -> bool {
+        matches!(self, Edge::TopLeft | Edge::TopRight | Edge::BottomLeft | Edge::BottomRight)
+    }
+}
+
+/// which edge the drag hit and where along it
+/// `at` goes 0.0 -> 1.0 top to bottom on left / right , left to right on top / bottom , always 0.0 on corners
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeHit {
+    pub edge : Edge,
+    pub at : f64,
+}
+
+impl EdgeHit {
+
+    /// "right@0.250" , what rides along get_tabs and comes back from chrome_ext
+    pub fn to_token(&self) -> String {
+        format!("{}@{:.3}" , self.edge.as_str() , self.at)
+    }
+
+    /// a bare "right" (older token) means the middle of that edge
+    pub fn from_token(token : &str) -> Option<EdgeHit> {
+        let (edge , at) = match token.split_once('@') {
+            Some((edge , at)) => (edge , at.parse::<f64>().ok()?),
+            None => (token , 0.5),
+        };
+
+        Some(EdgeHit { edge : Edge::parse(edge)? , at : at.clamp(0.0, 1.0) })
+    }
+}
+
+/// size of the screen the edge was hit on , px
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Monitor {
+    pub width : u64,
+    pub height : u64,
+}
+
+/// the window in front when the edge was hit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveWindow {
+    pub title : String,
+    /// process name on windows , the " - App" end of the title on linux
+    pub app : String,
+}
+
+/// words in a process name / title end that mean a chromium browser the extension runs in ,
+/// the same family `browser::Kind` launches ( firefox can't run it )
+const BROWSER_WORDS: [&str; 6] = ["chrome", "chromium", "brave", "edge", "msedge", "vivaldi"];
+
+impl ActiveWindow {
+    /// "chrome.exe" , "msedge.exe" , "Google Chrome" , "Brave" , "Microsoft Edge" , "Vivaldi" ...
+    /// whole words only , so an editor showing "notes - Knowledge" doesn't count
+    pub fn is_browser(&self) -> bool {
+        self.app
+            .to_lowercase()
+            .split(|c : char| !c.is_alphanumeric())
+            .any(|word| BROWSER_WORDS.contains(&word))
+    }
+}
+
+/// everything the edge checker knows about one trigger
+#[derive(Debug, Clone, PartialEq)]
+pub struct EdgeEvent {
+    pub hit : EdgeHit,
+    /// pointer position , px
+    pub cursor : (f64 , f64),
+    pub monitor : Monitor,
+    pub time : SystemTime,
+    pub window : ActiveWindow,
+}
+
+//pure part of the mouse hook , None when (x , y) is not inside the edge band
+pub fn hit_at(x : f64 , y : f64 , screen_w : f64 , screen_h : f64 , edge_screen : f64) -> Option<EdgeHit> {
+
+    let x_at_left = x <= edge_screen;
+    let x_at_right = x >= screen_w - edge_screen;
+    let y_at_top = y <= edge_screen;
+    let y_at_bottom = y >= screen_h - edge_screen;
+
+    let edge = match (x_at_left , x_at_right , y_at_top , y_at_bottom) {
+        (true , _ , true , _) => Edge::TopLeft,
+        (_ , true , true , _) => Edge::TopRight,
+        (true , _ , _ , true) => Edge::BottomLeft,
+        (_ , true , _ , true) => Edge::BottomRight,
+        (true , _ , _ , _) => Edge::Left,
+        (_ , true , _ , _) => Edge::Right,
+        (_ , _ , true , _) => Edge::Top,
+        (_ , _ , _ , true) => Edge::Bottom,
+        _ => return None,
+    };
+
+    let at = match edge {
+        Edge::Left | Edge::Right => y / screen_h,
+        Edge::Top | Edge::Bottom => x / screen_w,
+        _ => 0.0,
+    };
+
+    Some(EdgeHit { edge , at : at.clamp(0.0, 1.0) })
+}
+
+/// when a drag counts as "throw this to the other machine"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeConfig {
+    /// how close to the edge ( px ) the pointer has to get
+    pub band_px : f64,
+    /// how long the button has to be held before an edge counts
+    pub hold : Duration,
+    /// quiet time after a trigger
+    pub cooldown : Duration,
+}
+
+impl Default for EdgeConfig {
+    fn default() -> Self {
+        EdgeConfig { band_px : 15.0 , hold : Duration::from_millis(300) , cooldown : Duration::from_secs(10) }
+    }
+}
+
+pub fn edge_check<F>(config : EdgeConfig , on_edge : F) where F : Fn(EdgeEvent) + Send + Sync + 'static{
+
+    let draging = Arc::new(AtomicBool::new(false)); 
+    let drag_start = Arc::new(std::sync::Mutex::new(Instant::now()));
+    let last_trigger = Arc::new(std::sync::Mutex::new(Instant::now().checked_sub(config.cooldown).unwrap_or_else(Instant::now))); // start in the past
+
+
+    let draging_thread = draging.clone();
+    let drag_start_thread = drag_start.clone();
+
+    let on_edge = Arc::new(on_edge);
+    let on_edge_clone = on_edge.clone();
+
+    std::thread::spawn(move || {
+
+        listen(move | event :Event |{
+            // println!("{:?}", event);
+            match event.event_type {
+                EventType::ButtonPress(Button::Left) => {
+                    draging_thread.store(true , Ordering::SeqCst);
+                    //get pass ref and reset the data not change it 
+                    *drag_start_thread.lock().unwrap() = Instant::now();
+                }
+
+                EventType::ButtonRelease(Button::Left) => {
+                    draging_thread.store(false, Ordering::SeqCst);
+                }
+
+                EventType::MouseMove { x, y } if draging_thread.load(Ordering::SeqCst) => {
+                    let ( screen_w_u64 , screen_h_u64) = display_size().unwrap();
+                    let screen_w = screen_w_u64 as f64;
+                    let screen_h = screen_h_u64 as f64;
+                    let edge_screen = config.band_px;
+
+                    // println!("{:?}" , x);
+                    // println!("{:?}" , screen_w);
+                    if let Some(hit) = hit_at(x , y , screen_w , screen_h , edge_screen) {
+                        let held_for  =drag_start_thread.lock().unwrap().elapsed();
+                        let mut last = last_trigger.lock().unwrap();
+                        if held_for > config.hold && last.elapsed() > config.cooldown {
+                            //only asked once the drag qualifies , it's a syscall per mouse move otherwise
+                            if let Some(window) = active_window().filter(ActiveWindow::is_browser) {
+                                on_edge_clone(EdgeEvent {
+                                    hit,
+                                    cursor : (x , y),
+                                    monitor : Monitor { width : screen_w_u64 , height : screen_h_u64 },
+                                    time : SystemTime::now(),
+                                    window,
+                                });
+                                *last = Instant::now();
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }).expect("mouse hook failed");
+    });
+}
+
+
+#[cfg(target_os = "windows")]
+fn active_window() -> Option<ActiveWindow> {
+    use windows::Win32::{
+        UI::WindowsAndMessaging::{GetForegroundWindow, GetWindowTextW, GetWindowThreadProcessId},
+        System::ProcessStatus::K32GetModuleBaseNameA,
+        System::Threading::{OpenProcess, PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+        Foundation::{CloseHandle, MAX_PATH, HWND},
+    };
+
+    unsafe {
+        let fore_ground_window = GetForegroundWindow();
+        
+        // HWND wraps an isize, so checking .0 == 0 works, but HWND(0) is cleaner
+        if fore_ground_window == HWND(std::ptr::null_mut()) {
+            return None;
+        }
+
+        let mut pid = 0u32;
+        GetWindowThreadProcessId(fore_ground_window, Some(&mut pid));
+
+        if pid == 0 {
+            return None;
+        }
+
+        // FIX: OpenProcess returns Result<HANDLE>. Handle the Result, don't check for 0.
+        let process_handle = match OpenProcess(
+            PROCESS_QUERY_INFORMATION | PROCESS_VM_READ, 
+            false, 
+            pid
+        ) {
+            Ok(handle) => handle, // If successful, we get the HANDLE
+            Err(_) => return None, // If it fails (access denied, etc.), return None
+        };
+
+        let mut name_buf = [0u8; MAX_PATH as usize];
+        
+        let len = K32GetModuleBaseNameA(
+            process_handle,
+            None, // Use None instead of 0 for null HMODULE
+            &mut name_buf,
+        );
+
+        // Important: Close the handle to avoid memory leaks!
+        let _ = CloseHandle(process_handle);
+
+        if len == 0 {
+            return None;
+        }
+
+        let mut title_buf = [0u16; 512];
+        let title_len = GetWindowTextW(fore_ground_window, &mut title_buf).max(0) as usize;
+
+        Some(ActiveWindow {
+            title : String::from_utf16_lossy(&title_buf[..title_len]),
+            app : String::from_utf8_lossy(&name_buf[..len as usize]).to_string(),
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn active_window() -> Option<ActiveWindow> { 
+   
+   use x_win::get_active_window;
+
+   match get_active_window() {
+       Ok(window) => {
+            //"page - Google Chrome" , the app is whatever comes after the last -
+            let app = window.title.rsplit('-').next().unwrap_or("").trim().to_string();
+            Some(ActiveWindow { title : window.title , app })
+       }
+
+       Err(_) => {
+            eprintln!("Error getting active window");
+            None
+        }
+   }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W : f64 = 1920.0;
+    const H : f64 = 1080.0;
+
+    #[test]
+    fn every_chromium_browser_counts() {
+        let window = |app : &str| ActiveWindow { title : String::new() , app : app.to_string() };
+        for app in ["chrome.exe", "msedge.exe", "brave.exe", "vivaldi.exe", "Google Chrome", "Chromium", "Brave", "Microsoft\u{200b} Edge", "Vivaldi"] {
+            assert!(window(app).is_browser(), "{}", app);
+        }
+        for app in ["firefox.exe", "Mozilla Firefox", "Knowledge", "code.exe", ""] {
+            assert!(!window(app).is_browser(), "{}", app);
+        }
+    }
+
+    #[test]
+    fn middle_of_screen_is_no_edge() {
+        assert_eq!(hit_at(W / 2.0, H / 2.0, W, H, 15.0), None);
+    }
+
+    #[test]
+    fn sides_report_position_along_edge() {
+        assert_eq!(hit_at(0.0, H / 4.0, W, H, 15.0), Some(EdgeHit { edge: Edge::Left, at: 0.25 }));
+        assert_eq!(hit_at(W, H / 2.0, W, H, 15.0), Some(EdgeHit { edge: Edge::Right, at: 0.5 }));
+        assert_eq!(hit_at(W * 0.75, 0.0, W, H, 15.0), Some(EdgeHit { edge: Edge::Top, at: 0.75 }));
+        assert_eq!(hit_at(W * 0.25, H - 1.0, W, H, 15.0), Some(EdgeHit { edge: Edge::Bottom, at: 0.25 }));
+    }
+
+    #[test]
+    fn corners_win_over_sides() {
+        assert_eq!(hit_at(0.0, 0.0, W, H, 15.0).unwrap().edge, Edge::TopLeft);
+        assert_eq!(hit_at(W, 5.0, W, H, 15.0).unwrap().edge, Edge::TopRight);
+        assert_eq!(hit_at(3.0, H, W, H, 15.0).unwrap().edge, Edge::BottomLeft);
+        assert_eq!(hit_at(W, H, W, H, 15.0).unwrap().edge, Edge::BottomRight);
+    }
+
+    #[test]
+    fn token_round_trip() {
+        for edge in Edge::ALL {
+            let hit = EdgeHit { edge, at: 0.125 };
+            assert_eq!(EdgeHit::from_token(&hit.to_token()), Some(hit));
+            assert!(!hit.to_token().contains('-'));
+        }
+    }
+
+    #[test]
+    fn bare_edge_token_is_the_middle() {
+        assert_eq!(EdgeHit::from_token("left"), Some(EdgeHit { edge: Edge::Left, at: 0.5 }));
+        assert_eq!(EdgeHit::from_token("nope"), None);
+        assert_eq!(EdgeHit::from_token("left@x"), None);
+    }
+}
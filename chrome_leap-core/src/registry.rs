@@ -0,0 +1,156 @@
+//! which chrome_ext is on the other end of each ws connection
+//!
+//! chrome_ext ---- register { browser , profile , windows } ----> [registry] <---- edge checker asks who owns the front window
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrome_leap_proto::BrowserWindow;
+
+use crate::os_check::ActiveWindow;
+
+/// what one connected extension told us about itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Extension {
+    pub addr : SocketAddr,
+    pub browser : String,
+    pub profile : String,
+    pub windows : Vec<BrowserWindow>,
+}
+
+/// shared between local_ws and whoever wants to know , cheap to clone
+#[derive(Debug, Clone, Default)]
+pub struct Registry {
+    inner : Arc<Mutex<HashMap<SocketAddr , Extension>>>,
+}
+
+impl Registry {
+    fn map(&self) -> MutexGuard<'_ , HashMap<SocketAddr , Extension>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// true the first time `addr` registers , later calls only refresh its windows
+    pub fn register(&self , extension : Extension) -> bool {
+        self.map().insert(extension.addr , extension).is_none()
+    }
+
+    pub fn remove(&self , addr : SocketAddr) -> Option<Extension> {
+        self.map().remove(&addr)
+    }
+
+    pub fn get(&self , addr : SocketAddr) -> Option<Extension> {
+        self.map().get(&addr).cloned()
+    }
+
+    /// every registered extension , in no particular order
+    pub fn list(&self) -> Vec<Extension> {
+        self.map().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.map().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map().is_empty()
+    }
+
+    /// where a throw should open : an extension with a focused window , any one if none says so , None = nobody registered
+    pub fn opener(&self) -> Option<SocketAddr> {
+        let map = self.map();
+        map.values()
+            .filter(|ext| ext.windows.iter().any(|w| w.focused))
+            .min_by_key(|ext| ext.addr)
+            .or_else(|| map.values().min_by_key(|ext| ext.addr))
+            .map(|ext| ext.addr)
+    }
+
+    /// the extension that owns the window the os has in front , None = can't tell , ask everyone
+    pub fn owner(&self , window : &ActiveWindow) -> Option<SocketAddr> {
+        let map = self.map();
+
+        //os title is "<active tab title> - Google Chrome" , the longest tab title that fits wins
+        let by_title = map
+            .values()
+            .flat_map(|ext| ext.windows.iter().map(move |w| (ext.addr , w)))
+            .filter(|(_ , w)| !w.title.is_empty() && window.title.starts_with(&w.title))
+            .max_by_key(|(_ , w)| (w.focused , w.title.len()))
+            .map(|(addr , _)| addr);
+        if by_title.is_some() {
+            return by_title;
+        }
+
+        //no title to go by , trust the extensions if exactly one says it has focus
+        let mut focused = map.values().filter(|ext| ext.windows.iter().any(|w| w.focused));
+        match (focused.next() , focused.next()) {
+            (Some(ext) , None) => Some(ext.addr),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ext(port : u16 , profile : &str , windows : &[(i64 , &str , bool)]) -> Extension {
+        Extension {
+            addr : SocketAddr::from(([127, 0, 0, 1], port)),
+            browser : "chrome".to_string(),
+            profile : profile.to_string(),
+            windows : windows.iter().map(|(id , title , focused)| BrowserWindow { id : *id , title : title.to_string() , focused : *focused }).collect(),
+        }
+    }
+
+    fn front(title : &str) -> ActiveWindow {
+        ActiveWindow { title : title.to_string() , app : "Google Chrome".to_string() }
+    }
+
+    #[test]
+    fn register_then_refresh() {
+        let registry = Registry::default();
+        assert!(registry.register(ext(1, "work", &[(1, "Inbox", true)])));
+        assert!(!registry.register(ext(1, "work", &[(1, "Docs", true)])));
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.get(SocketAddr::from(([127, 0, 0, 1], 1))).unwrap().windows[0].title, "Docs");
+
+        registry.remove(SocketAddr::from(([127, 0, 0, 1], 1)));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn owner_by_window_title() {
+        let registry = Registry::default();
+        registry.register(ext(1, "work", &[(1, "Inbox", false), (2, "Pull requests", false)]));
+        registry.register(ext(2, "home", &[(7, "Inbox (3)", false)]));
+
+        assert_eq!(registry.owner(&front("Pull requests - Google Chrome")), Some(SocketAddr::from(([127, 0, 0, 1], 1))));
+        //"Inbox" fits both , the longer title is the better match
+        assert_eq!(registry.owner(&front("Inbox (3) - Google Chrome")), Some(SocketAddr::from(([127, 0, 0, 1], 2))));
+    }
+
+    #[test]
+    fn owner_by_focus_when_titles_dont_help() {
+        let registry = Registry::default();
+        registry.register(ext(1, "work", &[(1, "", true)]));
+        registry.register(ext(2, "home", &[(7, "", false)]));
+        assert_eq!(registry.owner(&front("New Tab - Google Chrome")), Some(SocketAddr::from(([127, 0, 0, 1], 1))));
+
+        registry.register(ext(2, "home", &[(7, "", true)]));
+        assert_eq!(registry.owner(&front("New Tab - Google Chrome")), None);
+    }
+
+    #[test]
+    fn one_opener_for_a_throw() {
+        let registry = Registry::default();
+        assert_eq!(registry.opener(), None);
+
+        registry.register(ext(2, "home", &[(7, "", false)]));
+        registry.register(ext(1, "work", &[(1, "", false)]));
+        assert_eq!(registry.opener(), Some(SocketAddr::from(([127, 0, 0, 1], 1))));
+
+        registry.register(ext(2, "home", &[(7, "", true)]));
+        assert_eq!(registry.opener(), Some(SocketAddr::from(([127, 0, 0, 1], 2))));
+    }
+}
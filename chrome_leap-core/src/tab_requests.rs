@@ -0,0 +1,185 @@
+//! get_tabs goes to the extension that owns the front window , or to every one when the registry can't tell
+//! only one answer may leave this machine
+//!
+//! forwarder ---- get_tabs { id } ----> chrome_ext ( one per profile )
+//! forwarder <--- tabs { id , focused } ---- chrome_ext
+//! the one whose window has focus wins , if nobody says so the only answer is used , several = nothing is sent
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrome_leap_proto::ClientMsg;
+
+/// how long the other extensions get once one of them is asked , they answer from a cache so this is plenty
+pub const REPLY_WAIT: Duration = Duration::from_millis(500);
+
+/// ids for get_tabs and tel , small so javascript numbers keep them exact
+pub fn next_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    NEXT.fetch_add(1 , Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+struct Request {
+    /// extensions connected when it went out
+    asked : usize,
+    replies : Vec<ClientMsg>,
+    /// a forwarder started the REPLY_WAIT clock
+    timed : bool,
+}
+
+/// get_tabs still waiting for answers
+#[derive(Debug, Default)]
+pub struct Requests {
+    open : HashMap<u64 , Request>,
+}
+
+impl Requests {
+    pub fn len(&self) -> usize {
+        self.open.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.open.is_empty()
+    }
+
+    /// once per get_tabs , where it is sent , false = that id is already out
+    pub fn ask(&mut self , id : u64 , asked : usize) -> bool {
+        if self.open.contains_key(&id) {
+            return false;
+        }
+        self.open.insert(id , Request { asked : asked.max(1) , replies : Vec::new() , timed : false });
+        true
+    }
+
+    /// the task of every extension it went to calls this , true only for the first , that one owns the timeout
+    /// a request that already settled is not opened again
+    pub fn start_clock(&mut self , id : u64) -> bool {
+        match self.open.get_mut(&id) {
+            Some(request) if !request.timed => {
+                request.timed = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// forget a get_tabs that never went out
+    pub fn cancel(&mut self , id : u64) {
+        self.open.remove(&id);
+    }
+
+    /// one extension answered , Some = settled and this is what goes on to the router
+    pub fn reply(&mut self , msg : ClientMsg) -> Option<ClientMsg> {
+        let ClientMsg::Tabs { id , focused , .. } = &msg else {
+            return None;
+        };
+        let (id , focused) = (*id , *focused);
+
+        //nobody asked , late , or made up , only answers to a get_tabs that is still out go on
+        let Some(request) = self.open.get_mut(&id) else {
+            println!("[WS] dropping tabs for get_tabs {} , not asked or already settled" , id);
+            return None;
+        };
+
+        if focused {
+            self.open.remove(&id);
+            return Some(msg);
+        }
+
+        request.replies.push(msg);
+        if request.replies.len() >= request.asked {
+            return self.expire(id);
+        }
+        None
+    }
+
+    /// REPLY_WAIT is over or everyone answered , the best of what came back
+    pub fn expire(&mut self , id : u64) -> Option<ClientMsg> {
+        let mut request = self.open.remove(&id)?;
+        match request.replies.len() {
+            0 => {
+                println!("[WS] no extension answered get_tabs {}" , id);
+                None
+            }
+            1 => request.replies.pop(),
+            n => {
+                println!("[WS] {} extensions answered get_tabs {} and none has focus , not guessing which one" , n , id);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tabs(id : u64 , focused : bool , url : &str) -> ClientMsg {
+        ClientMsg::Tabs {
+            id,
+            focused,
+            tabs : vec![chrome_leap_proto::TabInfo::from_url(url)],
+            edge : "right".to_string(),
+            window : None,
+        }
+    }
+
+    #[test]
+    fn focused_answer_wins_and_the_rest_are_late() {
+        let mut requests = Requests::default();
+        assert!(requests.ask(1, 2));
+        assert!(!requests.ask(1, 2));
+        assert!(requests.start_clock(1));
+        assert!(!requests.start_clock(1));
+
+        assert_eq!(requests.reply(tabs(1, true, "https://work.example")), Some(tabs(1, true, "https://work.example")));
+        assert!(requests.is_empty());
+        assert_eq!(requests.reply(tabs(1, false, "https://home.example")), None);
+    }
+
+    #[test]
+    fn unfocused_answers_wait_for_everyone() {
+        let mut requests = Requests::default();
+        requests.ask(2, 2);
+        assert_eq!(requests.reply(tabs(2, false, "https://home.example")), None);
+        assert_eq!(requests.len(), 1);
+
+        //two profiles , neither has focus , sending either could be wrong
+        assert_eq!(requests.reply(tabs(2, false, "https://work.example")), None);
+        assert!(requests.is_empty());
+    }
+
+    #[test]
+    fn a_single_answer_is_used_at_timeout() {
+        let mut requests = Requests::default();
+        requests.ask(3, 2);
+        assert_eq!(requests.reply(tabs(3, false, "https://a.example")), None);
+        assert_eq!(requests.expire(3), Some(tabs(3, false, "https://a.example")));
+        assert_eq!(requests.expire(3), None);
+
+        requests.ask(4, 1);
+        assert_eq!(requests.expire(4), None);
+    }
+
+    #[test]
+    fn answers_nobody_asked_for_are_dropped() {
+        let mut requests = Requests::default();
+        assert_eq!(requests.reply(tabs(0, true, "https://a.example")), None);
+        assert_eq!(requests.reply(tabs(9, true, "https://a.example")), None);
+    }
+
+    #[test]
+    fn a_settled_request_isnt_opened_again() {
+        let mut requests = Requests::default();
+        requests.ask(5, 2);
+        assert!(requests.start_clock(5));
+        assert!(requests.reply(tabs(5, true, "https://work.example")).is_some());
+
+        //the slower extension's task sees the get_tabs only now
+        assert!(!requests.start_clock(5));
+        assert_eq!(requests.reply(tabs(5, false, "https://home.example")), None);
+        assert!(requests.is_empty());
+    }
+}
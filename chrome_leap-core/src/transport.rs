@@ -0,0 +1,167 @@
+//! the byte pipe between two machines , plain tcp or quic
+//!
+//! everything above this ( handshake , GlobalMsg lines ) only sees a `BoxLink`
+
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::time::timeout;
+
+/// alpn both sides announce on quic
+pub const ALPN: &[u8] = b"chrome_leap";
+
+/// name the client asks for , the cert is pinned so this is only a label
+pub const SERVER_NAME: &str = "chrome_leap";
+
+/// longest handshake / auth line , anyone can send these before being checked
+pub const MAX_LINE: usize = 16 * 1024;
+
+pub trait Link: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Link for T {}
+
+pub type BoxLink = Box<dyn Link>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Quic,
+}
+
+impl Transport {
+    pub fn parse(s : &str) -> Option<Transport> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Some(Transport::Tcp),
+            "quic" => Some(Transport::Quic),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Tcp => "tcp",
+            Transport::Quic => "quic",
+        }
+    }
+}
+
+/// sha256 of a der certificate , lowercase hex
+pub fn fingerprint(der : &[u8]) -> String {
+    Sha256::digest(der).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+//one json line , only for the handshake / auth part before the session starts
+pub async fn send_msg<T : Serialize>(stream : &mut BufReader<BoxLink> , msg : &T) -> anyhow::Result<()> {
+    let json = serde_json::to_string(msg)?;
+    stream.write_all((json + "\n").as_bytes()).await?;
+    Ok(())
+}
+
+pub async fn recv_msg<T : DeserializeOwned>(stream : &mut BufReader<BoxLink> , wait : Duration) -> anyhow::Result<T> {
+    let line = recv_line(stream, wait).await?;
+    serde_json::from_str(&line).map_err(|e| anyhow!("unexpected message ( {} ) : {}", e, line.trim()))
+}
+
+/// one line of at most MAX_LINE bytes , a peer that never sends a newline can't grow it forever
+pub async fn recv_line<R : AsyncBufReadExt + Unpin>(stream : &mut R , wait : Duration) -> anyhow::Result<String> {
+    let mut line = String::new();
+    let n = timeout(wait, stream.take(MAX_LINE as u64).read_line(&mut line))
+        .await
+        .map_err(|_| anyhow!("peer said nothing for {:?}", wait))??;
+
+    if n == 0 {
+        bail!("peer closed the connection");
+    }
+    if !line.ends_with('\n') {
+        bail!("line over {} bytes , dropping the peer", MAX_LINE);
+    }
+    Ok(line)
+}
+
+//keep alive well under the idle timeout so a quiet link is not dropped ,
+//quic keeps the connection across an address change ( wifi roaming ) on its own
+pub fn quic_transport_config() -> Arc<quinn::TransportConfig> {
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(Some(Duration::from_secs(5)));
+    transport.max_idle_timeout(Some(Duration::from_secs(30).try_into().expect("30s fits in a quic varint")));
+    Arc::new(transport)
+}
+
+/// one bidirectional quic stream , holds the connection ( and client endpoint ) open while alive
+pub struct QuicLink {
+    send : quinn::SendStream,
+    recv : quinn::RecvStream,
+    _conn : quinn::Connection,
+    _endpoint : Option<quinn::Endpoint>,
+}
+
+impl QuicLink {
+    pub fn new(
+        conn : quinn::Connection,
+        (send , recv) : (quinn::SendStream , quinn::RecvStream),
+        endpoint : Option<quinn::Endpoint>,
+    ) -> QuicLink {
+        QuicLink { send , recv , _conn : conn , _endpoint : endpoint }
+    }
+}
+
+impl AsyncRead for QuicLink {
+    fn poll_read(self : Pin<&mut Self> , cx : &mut Context<'_> , buf : &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().recv).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for QuicLink {
+    fn poll_write(self : Pin<&mut Self> , cx : &mut Context<'_> , buf : &[u8]) -> Poll<std::io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.get_mut().send), cx, buf)
+    }
+
+    fn poll_flush(self : Pin<&mut Self> , cx : &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.get_mut().send), cx)
+    }
+
+    fn poll_shutdown(self : Pin<&mut Self> , cx : &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.get_mut().send), cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_is_sha256_hex() {
+        assert_eq!(
+            fingerprint(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn transport_parse() {
+        assert_eq!(Transport::parse("QUIC"), Some(Transport::Quic));
+        assert_eq!(Transport::parse("tcp"), Some(Transport::Tcp));
+        assert_eq!(Transport::parse("udp"), None);
+    }
+
+    #[tokio::test]
+    async fn pre_auth_lines_are_bounded() {
+        let wait = Duration::from_secs(1);
+        let mut ok = BufReader::new(&b"{\"type\":\"hello\"}\nrest"[..]);
+        assert_eq!(recv_line(&mut ok, wait).await.unwrap(), "{\"type\":\"hello\"}\n");
+
+        let endless = vec![b'a'; MAX_LINE * 4];
+        let mut endless = BufReader::new(&endless[..]);
+        assert!(recv_line(&mut endless, wait).await.unwrap_err().to_string().contains("over"));
+
+        assert!(recv_line(&mut BufReader::new(&b""[..]), wait).await.is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! which urls may leave this machine and which may be opened on it
+//!
+//! chrome_ext ---- tabs ----> [policy] ---- tcp ----> another_computer ---- [policy] ----> chrome_ext / browser
+//! a url is a command line argument on the receiving side , so anything odd is refused , not cleaned up
+//! urls are read the way a browser reads them ( WHATWG ) , so `https:evil.example` has a host like `https://evil.example`
+
+use std::fmt;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Deserializer};
+use url::{Host, Url};
+
+use chrome_leap_proto::TabInfo;
+
+/// allow / deny lists , deny always wins
+#[derive(Debug, Clone, PartialEq)]
+pub struct UrlPolicy {
+    /// lowercase , without the ':'
+    pub schemes : Vec<String>,
+    /// "example.com" also covers its subdomains , empty = any host
+    pub allow_domains : Vec<String>,
+    pub deny_domains : Vec<String>,
+    /// regexes over the parsed url , case insensitive , empty = any url
+    pub allow_patterns : Vec<Pattern>,
+    pub deny_patterns : Vec<Pattern>,
+}
+
+/// a regex from the config , searched anywhere in the url unless anchored with ^ / $
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn new(source : &str) -> Result<Pattern , String> {
+        RegexBuilder::new(source)
+            .case_insensitive(true)
+            .build()
+            .map(Pattern)
+            .map_err(|e| format!("bad url pattern '{}' : {}" , source , e))
+    }
+
+    /// every entry of a list , the first bad one is the error
+    pub fn list<S : AsRef<str>>(sources : &[S]) -> Result<Vec<Pattern> , String> {
+        sources.iter().map(|s| Pattern::new(s.as_ref())).collect()
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    pub fn is_match(&self , url : &str) -> bool {
+        self.0.is_match(url)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self , other : &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self , f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+//config.toml strings , a bad regex is a config error at its line
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Self , D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+impl Default for UrlPolicy {
+    fn default() -> Self {
+        UrlPolicy {
+            schemes : vec!["http".to_string() , "https".to_string()],
+            allow_domains : Vec::new(),
+            deny_domains : Vec::new(),
+            allow_patterns : Vec::new(),
+            deny_patterns : Vec::new(),
+        }
+    }
+}
+
+/// a url the policy turned away and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejected {
+    pub url : String,
+    pub reason : String,
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self , f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f , "{} ( {} )" , self.url , self.reason)
+    }
+}
+
+impl UrlPolicy {
+    /// Ok(the url as parsed) , pass that one on so what is opened is exactly what was checked
+    /// Err(reason) = this url must not be sent or opened
+    pub fn check(&self , url : &str) -> Result<String , String> {
+        //never configurable , these turn a url into something else on a command line
+        if url.is_empty() {
+            return Err("empty".to_string());
+        }
+        if url.starts_with('-') {
+            return Err("looks like a command line flag".to_string());
+        }
+        if url.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"') {
+            return Err("has spaces , quotes or control characters".to_string());
+        }
+
+        let parsed = Url::parse(url).map_err(|e| format!("not a url : {}" , e))?;
+        let scheme = parsed.scheme();
+        if !self.schemes.iter().any(|s| s.eq_ignore_ascii_case(scheme)) {
+            return Err(format!("scheme '{}' is not allowed" , scheme));
+        }
+
+        let host = host(&parsed);
+        if host.is_none() && matches!(scheme , "http" | "https") {
+            return Err("web url without a host".to_string());
+        }
+        if let Some(host) = host {
+            if self.deny_domains.iter().any(|d| in_domain(&host , d)) {
+                return Err(format!("domain '{}' is denied" , host));
+            }
+            if !self.allow_domains.is_empty() && !self.allow_domains.iter().any(|d| in_domain(&host , d)) {
+                return Err(format!("domain '{}' is not allowed" , host));
+            }
+        } else if !self.allow_domains.is_empty() {
+            return Err("no host to check against allow_domains".to_string());
+        }
+
+        //the parsed form , `https:\\a.example/logout` is matched as `https://a.example/logout`
+        let normalized = parsed.as_str();
+        if let Some(pattern) = self.deny_patterns.iter().find(|p| p.is_match(normalized)) {
+            return Err(format!("matches denied pattern '{}'" , pattern));
+        }
+        if !self.allow_patterns.is_empty() && !self.allow_patterns.iter().any(|p| p.is_match(normalized)) {
+            return Err("matches no allowed pattern".to_string());
+        }
+        Ok(normalized.to_string())
+    }
+
+    /// the tabs that pass with their url normalized , and the ones that don't with a reason each
+    pub fn filter(&self , tabs : Vec<TabInfo>) -> (Vec<TabInfo> , Vec<Rejected>) {
+        let mut kept = Vec::with_capacity(tabs.len());
+        let mut rejected = Vec::new();
+        for tab in tabs {
+            match self.check(&tab.url) {
+                Ok(url) => kept.push(TabInfo { url , ..tab }),
+                Err(reason) => rejected.push(Rejected { url : tab.url , reason }),
+            }
+        }
+        (kept , rejected)
+    }
+}
+
+//lowercase , punycode , no trailing dot , None for urls without one ( mailto: , about:blank )
+fn host(url : &Url) -> Option<String> {
+    let host = match url.host()? {
+        Host::Domain(domain) => domain.trim_end_matches('.').to_string(),
+        //ipv6 without the [ ] so it compares like the lists are written
+        Host::Ipv4(ip) => ip.to_string(),
+        Host::Ipv6(ip) => ip.to_string(),
+    };
+    (!host.is_empty()).then_some(host)
+}
+
+fn in_domain(host : &str , domain : &str) -> bool {
+    let domain = domain.trim_start_matches("*.").trim_end_matches('.');
+    //"bücher.example" in the list matches the xn-- form the parser gives us
+    let domain = match Host::parse(domain) {
+        Ok(Host::Domain(ascii)) => ascii,
+        _ => domain.to_ascii_lowercase(),
+    };
+    host == domain || host.strip_suffix(&domain).is_some_and(|sub| sub.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_web_only() {
+        let policy = UrlPolicy::default();
+        assert_eq!(policy.check("https://a.example/x?y=1").unwrap(), "https://a.example/x?y=1");
+        //the launcher gets the parsed form , not the string that was sent
+        assert_eq!(policy.check("HTTPS:\\\\A.Example\\x").unwrap(), "https://a.example/x");
+        assert_eq!(policy.check("https://bücher.example/").unwrap(), "https://xn--bcher-kva.example/");
+        assert!(policy.check("HTTP://a.example").is_ok());
+        for url in ["chrome://settings", "file:///etc/passwd", "javascript:alert(1)", "about:blank", "no-scheme"] {
+            assert!(policy.check(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn command_line_tricks_are_refused() {
+        let policy = UrlPolicy::default();
+        assert!(policy.check("--renderer-cmd-prefix=calc").unwrap_err().contains("flag"));
+        assert!(policy.check("https://a.example/ --incognito").is_err());
+        assert!(policy.check("https://a.example/\"&calc").is_err());
+        assert!(policy.check("").is_err());
+    }
+
+    #[test]
+    fn domains() {
+        let policy = UrlPolicy {
+            allow_domains : vec!["example.com".to_string()],
+            deny_domains : vec!["ads.example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.check("https://example.com").is_ok());
+        assert!(policy.check("https://user:pw@Docs.Example.com:8443/a").is_ok());
+        assert!(policy.check("https://x.ads.example.com/").unwrap_err().contains("denied"));
+        assert!(policy.check("https://notexample.com/").unwrap_err().contains("not allowed"));
+        assert!(policy.check("https://example.com.evil.test/").is_err());
+    }
+
+    #[test]
+    fn hosts_are_read_like_a_browser_reads_them() {
+        let policy = UrlPolicy { deny_domains : vec!["evil.example".to_string()] , ..Default::default() };
+        for url in ["https:evil.example", "https:\\\\evil.example", "https:/evil.example", "HTTPS://EVIL.example./x", "https://a@evil.example"] {
+            assert!(policy.check(url).unwrap_err().contains("denied"), "{}", url);
+        }
+        assert!(policy.check("https://[::1]/").is_ok());
+        assert!(policy.check("https://").is_err());
+
+        let allow = UrlPolicy { allow_domains : vec!["bücher.example".to_string()] , ..Default::default() };
+        assert!(allow.check("https://xn--bcher-kva.example/").is_ok());
+        assert!(allow.check("https:\\\\other.example").unwrap_err().contains("not allowed"));
+    }
+
+    #[test]
+    fn patterns() {
+        let policy = UrlPolicy {
+            deny_patterns : Pattern::list(&["/logout"]).unwrap(),
+            allow_patterns : Pattern::list(&["^https://"]).unwrap(),
+            ..Default::default()
+        };
+        assert!(policy.check("https://a.example/inbox").is_ok());
+        assert!(policy.check("https://a.example/LogOut?now").unwrap_err().contains("denied pattern"));
+        assert!(policy.check("https:\\\\a.example\\logout").unwrap_err().contains("denied pattern"));
+        assert!(policy.check("http://a.example/").unwrap_err().contains("no allowed pattern"));
+        assert!(Pattern::new("a(").unwrap_err().contains("bad url pattern"));
+    }
+
+    #[test]
+    fn filter_reports_what_it_drops() {
+        let tabs = vec![TabInfo::from_url("https://a.example"), TabInfo::from_url("file:///etc/passwd")];
+        let (kept , rejected) = UrlPolicy::default().filter(tabs);
+        assert_eq!(kept, vec![TabInfo::from_url("https://a.example/")]);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].to_string(), "file:///etc/passwd ( scheme 'file' is not allowed )");
+    }
+}
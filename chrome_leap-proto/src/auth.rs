@@ -0,0 +1,85 @@
+//! pairing and authentication , right after hello_ack
+//!
+//! pairing ( once , `chrome_leap-client pair` while the server runs with `--pair` ) :
+//! client ---- pair_request ----> server        server prints a short code
+//! client <--- pair_challenge ---- server       its spake2 half , user types the code on the client
+//! client ---- pair_confirm ----> server        our spake2 half + proof we ended with the same key
+//! client <--- pair_ok / auth_fail ---- server  both sides store the shared secret , one wrong code closes pairing
+//!
+//! every other connection :
+//! client ---- auth ----> server
+//! client <--- auth_challenge ---- server
+//! client ---- auth_proof ----> server
+//! client <--- auth_ok / auth_fail ---- server  every line after this carries an hmac
+//!
+//! keys , nonces and macs are hex strings
+
+use serde::{Deserialize, Serialize};
+
+/// v8 changed pairing to spake2 , older clients can still auth but not pair
+pub const MIN_PAIR_VERSION: u32 = 8;
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum AuthMsg {
+    #[serde(rename = "pair_request")]
+    PairRequest {},
+
+    #[serde(rename = "pair_challenge")]
+    PairChallenge { pub_key: String },
+
+    #[serde(rename = "pair_confirm")]
+    PairConfirm { pub_key: String, mac: String },
+
+    #[serde(rename = "pair_ok")]
+    PairOk { mac: String },
+
+    #[serde(rename = "auth")]
+    Auth { nonce: String },
+
+    #[serde(rename = "auth_challenge")]
+    AuthChallenge { nonce: String },
+
+    #[serde(rename = "auth_proof")]
+    AuthProof { mac: String },
+
+    #[serde(rename = "auth_ok")]
+    AuthOk { mac: String },
+
+    #[serde(rename = "auth_fail")]
+    AuthFail { reason: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn auth_msg_round_trip() {
+        let msgs = [
+            AuthMsg::PairRequest {},
+            AuthMsg::PairChallenge { pub_key: "bb".to_string() },
+            AuthMsg::PairConfirm { pub_key: "aa".to_string(), mac: "cc".to_string() },
+            AuthMsg::PairOk { mac: "dd".to_string() },
+            AuthMsg::Auth { nonce: "ee".to_string() },
+            AuthMsg::AuthChallenge { nonce: "ff".to_string() },
+            AuthMsg::AuthProof { mac: "00".to_string() },
+            AuthMsg::AuthOk { mac: "11".to_string() },
+            AuthMsg::AuthFail { reason: "nope".to_string() },
+        ];
+
+        for msg in msgs {
+            let text = serde_json::to_string(&msg).unwrap();
+            assert_eq!(serde_json::from_str::<AuthMsg>(&text).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn auth_wire_shape() {
+        assert_eq!(
+            serde_json::to_value(AuthMsg::Auth { nonce: "ab".to_string() }).unwrap(),
+            json!({ "action": "auth", "nonce": "ab" })
+        );
+    }
+}
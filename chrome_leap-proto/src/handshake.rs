@@ -0,0 +1,186 @@
+//! hello / hello_ack exchange , first line on every tcp (24811) connection
+//!
+//! client ---- hello ----> server
+//! client <--- hello_ack / hello_reject ---- server
+
+use serde::{Deserialize, Serialize};
+
+use crate::PROTOCOL_VERSION;
+
+/// oldest peer version we still talk to , v6 peers still send newline ended lines after auth
+pub const MIN_PROTOCOL_VERSION: u32 = 7;
+
+/// message kinds this build understands on the tcp link
+pub const MSG_KINDS: &[&str] = &["tabs", "ack", "nack", "ping", "pong"];
+
+/// peer can open tabs it receives
+pub const CAP_OPEN_TABS: &str = "open_tabs";
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum HandshakeMsg {
+    #[serde(rename = "hello")]
+    Hello(Hello),
+
+    #[serde(rename = "hello_ack")]
+    HelloAck(Hello),
+
+    #[serde(rename = "hello_reject")]
+    HelloReject { version: u32, reason: String },
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct Hello {
+    pub version: u32,
+    pub hostname: String,
+    /// stable name the server's config.toml uses for this machine , empty = same as hostname
+    #[serde(default)]
+    pub device_id: String,
+    pub kinds: Vec<String>,
+    pub capabilities: Vec<String>,
+}
+
+impl Hello {
+    pub fn new(hostname: impl Into<String>, capabilities: &[&str]) -> Self {
+        Hello {
+            version: PROTOCOL_VERSION,
+            hostname: hostname.into(),
+            device_id: String::new(),
+            kinds: MSG_KINDS.iter().map(|k| k.to_string()).collect(),
+            capabilities: capabilities.iter().map(|c| c.to_string()).collect(),
+        }
+    }
+
+    pub fn with_device_id(mut self, device_id: impl Into<String>) -> Self {
+        self.device_id = device_id.into();
+        self
+    }
+
+    /// what the other side keys this machine by
+    pub fn id(&self) -> &str {
+        if self.device_id.is_empty() {
+            &self.hostname
+        } else {
+            &self.device_id
+        }
+    }
+
+    /// Err(reason) when we can't talk to whoever sent this hello
+    pub fn check(&self) -> Result<(), String> {
+        if self.version < MIN_PROTOCOL_VERSION || self.version > PROTOCOL_VERSION {
+            return Err(format!(
+                "incompatible protocol version : peer '{}' speaks v{} , this build speaks v{}..=v{} , update the older side",
+                self.hostname, self.version, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION
+            ));
+        }
+
+        let missing: Vec<&str> = MSG_KINDS
+            .iter()
+            .copied()
+            .filter(|k| !self.kinds.iter().any(|p| p == k))
+            .collect();
+        if !missing.is_empty() {
+            return Err(format!(
+                "peer '{}' does not support message kinds : {:?}",
+                self.hostname, missing
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn has_capability(&self, cap: &str) -> bool {
+        self.capabilities.iter().any(|c| c == cap)
+    }
+}
+
+/// what the server answers to a hello
+pub fn answer(hello: &Hello, ours: Hello) -> HandshakeMsg {
+    match hello.check() {
+        Ok(()) => HandshakeMsg::HelloAck(ours),
+        Err(reason) => HandshakeMsg::HelloReject { version: PROTOCOL_VERSION, reason },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn hello_round_trip() {
+        let msg = HandshakeMsg::Hello(Hello::new("laptop", &[CAP_OPEN_TABS]));
+        let text = serde_json::to_string(&msg).unwrap();
+        assert_eq!(serde_json::from_str::<HandshakeMsg>(&text).unwrap(), msg);
+    }
+
+    #[test]
+    fn hello_wire_shape() {
+        let msg = HandshakeMsg::Hello(Hello::new("laptop", &[CAP_OPEN_TABS]));
+        assert_eq!(
+            serde_json::to_value(msg).unwrap(),
+            json!({
+                "action": "hello",
+                "version": PROTOCOL_VERSION,
+                "hostname": "laptop",
+                "device_id": "",
+                "kinds": ["tabs", "ack", "nack", "ping", "pong"],
+                "capabilities": ["open_tabs"],
+            })
+        );
+    }
+
+    #[test]
+    fn device_id_defaults_to_hostname() {
+        // v3 peers from before device ids
+        let old = r#"{"action":"hello","version":3,"hostname":"laptop","kinds":["tabs"],"capabilities":[]}"#;
+        match serde_json::from_str::<HandshakeMsg>(old).unwrap() {
+            HandshakeMsg::Hello(hello) => assert_eq!(hello.id(), "laptop"),
+            other => panic!("expected hello , got {:?}", other),
+        }
+
+        let two = Hello::new("laptop", &[]).with_device_id("laptop-work");
+        assert_eq!(two.id(), "laptop-work");
+    }
+
+    #[test]
+    fn same_version_is_acked() {
+        let theirs = Hello::new("laptop", &[]);
+        let ours = Hello::new("desktop", &[]);
+        assert_eq!(answer(&theirs, ours.clone()), HandshakeMsg::HelloAck(ours));
+    }
+
+    #[test]
+    fn old_version_is_rejected() {
+        let mut theirs = Hello::new("laptop", &[]);
+        theirs.version = MIN_PROTOCOL_VERSION - 1;
+        match answer(&theirs, Hello::new("desktop", &[])) {
+            HandshakeMsg::HelloReject { version, reason } => {
+                assert_eq!(version, PROTOCOL_VERSION);
+                assert!(reason.contains("incompatible protocol version"));
+            }
+            other => panic!("expected reject , got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn newer_version_is_rejected() {
+        let mut theirs = Hello::new("laptop", &[]);
+        theirs.version = PROTOCOL_VERSION + 1;
+        assert!(theirs.check().is_err());
+    }
+
+    #[test]
+    fn missing_kind_is_rejected() {
+        let mut theirs = Hello::new("laptop", &[]);
+        theirs.kinds.clear();
+        assert!(theirs.check().unwrap_err().contains("tabs"));
+    }
+
+    #[test]
+    fn pre_handshake_message_is_not_a_hello() {
+        // what a v1 server used to send straight away
+        let v1 = r#"{"action":"tabs","tabs":[],"time":"1"}"#;
+        assert!(serde_json::from_str::<HandshakeMsg>(v1).is_err());
+    }
+}
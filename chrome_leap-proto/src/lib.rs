@@ -0,0 +1,393 @@
+//! wire messages shared by chrome_leap-server and chrome_leap-client
+//!
+//! local  : forwarder <---- ws ----> chrome_ext     (ServerMsg / ClientMsg)
+//! global : forwarder <---- tcp ---> another_computer (GlobalMsg)
+
+use serde::{Deserialize, Serialize};
+
+pub mod auth;
+pub mod handshake;
+
+pub use auth::AuthMsg;
+pub use handshake::{Hello, HandshakeMsg};
+
+/// bump this every time a message below changes shape
+/// v2 - hello / hello_ack before any GlobalMsg
+/// v3 - auth ( or pairing ) after hello_ack , hmac on every line after that
+/// v4 - tabs are TabInfo objects + an optional window instead of bare urls
+/// v5 - tabs carry an id , the receiver answers with ack / nack
+/// v6 - ping / pong heartbeats so a half-open link is noticed
+/// v7 - after auth every sealed line goes in a length prefixed frame instead of ending in \n
+/// v8 - pairing is spake2 , the client sends its half with pair_confirm
+pub const PROTOCOL_VERSION: u32 = 8;
+
+/// one tab as chrome_ext sees it , only `url` has to be there
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct TabInfo {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub pinned: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<TabGroup>,
+    /// the tab that was in front when the edge was hit
+    #[serde(default)]
+    pub active: bool,
+    /// position in its window , 0 = leftmost
+    #[serde(default)]
+    pub index: u32,
+    #[serde(default)]
+    pub incognito: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fav_icon_url: Option<String>,
+}
+
+impl TabInfo {
+    pub fn from_url(url: impl Into<String>) -> Self {
+        TabInfo { url: url.into(), ..Default::default() }
+    }
+}
+
+/// chrome tab group , the id is only meaningful on the sending machine
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct TabGroup {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    /// chrome's names : grey , blue , red , yellow , green , pink , purple , cyan , orange
+    #[serde(default)]
+    pub color: String,
+}
+
+/// the window the tabs came from , anything unknown is left out
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct WindowInfo {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// normal , minimized , maximized , fullscreen
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+/// forwarder ---- ws ----> chrome_ext
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum ServerMsg {
+    // { action : get_tabs , id : 3 , edge : left } , every extension answers with the same id
+    #[serde(rename = "get_tabs")]
+    GetTabs {
+        #[serde(default)]
+        id: u64,
+        edge: String,
+    },
+
+    // { action : tel , id : 4 , tabs : [...] , window : {...} } , tabs another computer threw at us , answered with opened
+    #[serde(rename = "tel")]
+    Tel {
+        #[serde(default)]
+        id: u64,
+        tabs: Vec<TabInfo>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window: Option<WindowInfo>,
+    },
+}
+
+/// chrome_ext ---- ws ----> forwarder
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum ClientMsg {
+    //THIS IS VARIANT SO { action : tabs , id : 3 , focused : true , tabs : [...] , edge : left , window : {...} }
+    #[serde(rename = "tabs")]
+    Tabs {
+        /// the get_tabs this answers , 0 = an older extension that doesn't echo it
+        #[serde(default)]
+        id: u64,
+        /// the window these tabs came from has focus right now
+        #[serde(default)]
+        focused: bool,
+        #[serde(deserialize_with = "tabs_or_urls")]
+        tabs: Vec<TabInfo>,
+        edge: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window: Option<WindowInfo>,
+    },
+
+    // { action : register , browser : chrome , profile : "...", windows : [...] } , on connect and whenever focus moves
+    #[serde(rename = "register")]
+    Register {
+        browser: String,
+        /// random id the extension keeps per profile
+        profile: String,
+        #[serde(default)]
+        windows: Vec<BrowserWindow>,
+    },
+
+    // { action : opened , id : 4 , failed : [...] } or { action : opened , id : 4 , error : "..." } , the answer to a tel
+    #[serde(rename = "opened")]
+    Opened {
+        id: u64,
+        #[serde(default)]
+        failed: Vec<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// one window of a registered extension
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Default)]
+pub struct BrowserWindow {
+    pub id: i64,
+    /// title of its active tab , the os shows it as "<title> - Google Chrome"
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub focused: bool,
+}
+
+//older extensions send plain url strings
+fn tabs_or_urls<'de, D>(deserializer: D) -> Result<Vec<TabInfo>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TabOrUrl {
+        Url(String),
+        Tab(TabInfo),
+    }
+
+    let tabs = Vec::<TabOrUrl>::deserialize(deserializer)?;
+    Ok(tabs
+        .into_iter()
+        .enumerate()
+        .map(|(i, tab)| match tab {
+            TabOrUrl::Url(url) => TabInfo { index: i as u32, ..TabInfo::from_url(url) },
+            TabOrUrl::Tab(tab) => tab,
+        })
+        .collect())
+}
+
+/// forwarder ---- tcp ----> another_computer
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "action")]
+pub enum GlobalMsg {
+    #[serde(rename = "tabs")]
+    Tabs {
+        /// unique per sender , the ack / nack carries it back
+        id: u64,
+        tabs: Vec<TabInfo>,
+        time: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        window: Option<WindowInfo>,
+    },
+
+    // receiver ---- ack ----> sender , the tabs were opened , `failed` = urls that didn't make it
+    #[serde(rename = "ack")]
+    Ack {
+        id: u64,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        failed: Vec<String>,
+    },
+
+    // receiver ---- nack ----> sender , nothing was opened
+    #[serde(rename = "nack")]
+    Nack { id: u64, reason: String },
+
+    // either side ---- ping ----> other side ---- pong ----> , only says the link is still alive
+    #[serde(rename = "ping")]
+    Ping,
+
+    #[serde(rename = "pong")]
+    Pong,
+}
+
+impl GlobalMsg {
+    /// the transfer this is about , None for heartbeats
+    pub fn id(&self) -> Option<u64> {
+        match self {
+            GlobalMsg::Tabs { id, .. } | GlobalMsg::Ack { id, .. } | GlobalMsg::Nack { id, .. } => Some(*id),
+            GlobalMsg::Ping | GlobalMsg::Pong => None,
+        }
+    }
+}
+
+/// unix time in ns as a string , used as `GlobalMsg::Tabs::time`
+pub fn time_now() -> String {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn round_trip<T>(msg: &T) -> T
+    where
+        T: Serialize + for<'de> Deserialize<'de>,
+    {
+        let text = serde_json::to_string(msg).unwrap();
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn server_msg_round_trip() {
+        let msg = ServerMsg::GetTabs { id: 3, edge: "left".to_string() };
+        assert_eq!(round_trip(&msg), msg);
+    }
+
+    fn rich_tab() -> TabInfo {
+        TabInfo {
+            url: "https://a.example".to_string(),
+            title: Some("A".to_string()),
+            pinned: true,
+            group: Some(TabGroup { id: 7, name: "work".to_string(), color: "blue".to_string() }),
+            active: true,
+            index: 2,
+            incognito: false,
+            fav_icon_url: Some("https://a.example/favicon.ico".to_string()),
+        }
+    }
+
+    #[test]
+    fn client_msg_round_trip() {
+        let msg = ClientMsg::Tabs {
+            id: 3,
+            focused: true,
+            tabs: vec![rich_tab(), TabInfo::from_url("https://b.example")],
+            edge: "right".to_string(),
+            window: Some(WindowInfo { left: Some(-1920), top: Some(0), width: Some(1280), height: Some(800), state: Some("normal".to_string()) }),
+        };
+        assert_eq!(round_trip(&msg), msg);
+    }
+
+    #[test]
+    fn global_msg_round_trip() {
+        let msg = GlobalMsg::Tabs {
+            id: 7,
+            tabs: vec![rich_tab()],
+            time: time_now(),
+            window: None,
+        };
+        assert_eq!(round_trip(&msg), msg);
+
+        let ack = GlobalMsg::Ack { id: 7, failed: vec!["https://a.example".to_string()] };
+        assert_eq!(round_trip(&ack), ack);
+        let nack = GlobalMsg::Nack { id: 7, reason: "no browser".to_string() };
+        assert_eq!(round_trip(&nack), nack);
+    }
+
+    // the extension (background.js) speaks this exact shape , keep it stable
+    #[test]
+    fn wire_shape_matches_extension() {
+        let get_tabs = serde_json::to_value(ServerMsg::GetTabs { id: 3, edge: "left".to_string() }).unwrap();
+        assert_eq!(get_tabs, json!({ "action": "get_tabs", "id": 3, "edge": "left" }));
+
+        let tel = serde_json::to_value(ServerMsg::Tel { id: 4, tabs: vec![TabInfo::from_url("https://a.example")], window: None }).unwrap();
+        assert_eq!(
+            tel,
+            json!({ "action": "tel", "id": 4, "tabs": [{ "url": "https://a.example", "pinned": false, "active": false, "index": 0, "incognito": false }] })
+        );
+        let opened: ClientMsg = serde_json::from_value(json!({ "action": "opened", "id": 4 })).unwrap();
+        assert_eq!(opened, ClientMsg::Opened { id: 4, failed: vec![], error: None });
+
+        let tabs: ClientMsg = serde_json::from_value(json!({
+            "action": "tabs",
+            "id": 3,
+            "focused": true,
+            "tabs": [
+                { "url": "https://a.example", "title": "A", "active": true, "index": 0,
+                  "group": { "id": 7, "name": "work", "color": "blue" } },
+            ],
+            "edge": "left",
+            "window": { "left": 0, "top": 0, "width": 800, "height": 600, "state": "maximized" },
+        }))
+        .unwrap();
+        let ClientMsg::Tabs { id, focused, tabs, edge, window } = tabs else {
+            panic!("expected tabs");
+        };
+        assert_eq!((id, focused), (3, true));
+        assert_eq!(edge, "left");
+        assert_eq!(tabs[0].title.as_deref(), Some("A"));
+        assert_eq!(tabs[0].group.as_ref().map(|g| g.color.as_str()), Some("blue"));
+        assert_eq!(window.and_then(|w| w.state).as_deref(), Some("maximized"));
+    }
+
+    #[test]
+    fn register_wire_shape() {
+        let register: ClientMsg = serde_json::from_value(json!({
+            "action": "register",
+            "browser": "chrome",
+            "profile": "7f3a",
+            "windows": [{ "id": 12, "title": "Inbox", "focused": true }, { "id": 13 }],
+        }))
+        .unwrap();
+        assert_eq!(
+            register,
+            ClientMsg::Register {
+                browser: "chrome".to_string(),
+                profile: "7f3a".to_string(),
+                windows: vec![
+                    BrowserWindow { id: 12, title: "Inbox".to_string(), focused: true },
+                    BrowserWindow { id: 13, ..Default::default() },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn old_extension_urls_still_parse() {
+        let tabs: ClientMsg = serde_json::from_value(json!({
+            "action": "tabs",
+            "tabs": ["https://a.example", "https://b.example"],
+            "edge": "left",
+        }))
+        .unwrap();
+        assert_eq!(
+            tabs,
+            ClientMsg::Tabs {
+                id: 0,
+                focused: false,
+                tabs: vec![
+                    TabInfo::from_url("https://a.example"),
+                    TabInfo { index: 1, ..TabInfo::from_url("https://b.example") },
+                ],
+                edge: "left".to_string(),
+                window: None,
+            }
+        );
+    }
+
+    #[test]
+    fn global_msg_wire_shape() {
+        let msg = GlobalMsg::Tabs { id: 3, tabs: vec![TabInfo::from_url("https://a.example")], time: "1".to_string(), window: None };
+        assert_eq!(
+            serde_json::to_value(msg).unwrap(),
+            json!({
+                "action": "tabs",
+                "id": 3,
+                "tabs": [{ "url": "https://a.example", "pinned": false, "active": false, "index": 0, "incognito": false }],
+                "time": "1",
+            })
+        );
+        assert_eq!(serde_json::to_value(GlobalMsg::Ack { id: 3, failed: vec![] }).unwrap(), json!({ "action": "ack", "id": 3 }));
+        assert_eq!(serde_json::to_value(GlobalMsg::Ping).unwrap(), json!({ "action": "ping" }));
+        assert_eq!(serde_json::from_value::<GlobalMsg>(json!({ "action": "pong" })).unwrap(), GlobalMsg::Pong);
+    }
+
+    #[test]
+    fn unknown_action_is_rejected() {
+        assert!(serde_json::from_str::<GlobalMsg>(r#"{"action":"nope"}"#).is_err());
+    }
+}
@@ -1,324 +1,617 @@
 mod utils;
 
-use std::time::{SystemTime ,UNIX_EPOCH};
 use futures_util::lock::Mutex;
-use futures_util::{StreamExt, SinkExt};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::{accept_async, tungstenite::protocol::Message};
+use tokio::io::BufReader;
+use tokio::net::TcpListener;
 
-use tokio::sync::{broadcast, mpsc};
-use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, mpsc, oneshot, watch};
 use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, sleep_until, Instant};
+
+use crate::utils::config;
+use crate::utils::handshake::{self, AuthOutcome, PairWindow};
+use crate::utils::layout::Layout;
+use crate::utils::outbox::Outbox;
+use crate::utils::quic;
+use crate::utils::reload::ConfigWatch;
+use chrome_leap_core::browser::BrowserLauncher;
+use chrome_leap_core::delivery::{self, Due, PeerReplies, Pending, Retry};
+use chrome_leap_core::discovery;
+use chrome_leap_core::frame::{write_frame, FrameReader};
+use chrome_leap_core::{local_ws, log};
+use chrome_leap_core::local_ws::{Extensions, LocalEvent};
+use chrome_leap_core::registry::Registry;
+use chrome_leap_core::url_policy::UrlPolicy;
+use chrome_leap_core::os_check::{EdgeHit, edge_check};
+use chrome_leap_core::transport::{BoxLink, QuicLink, Transport};
+use chrome_leap_proto::{ClientMsg, GlobalMsg, time_now};
 
-use std::fs;
-use crate::utils::os_check::{Edge, edge_check};
-
-
-#[derive(Serialize)]
-#[serde(tag = "action")]
-enum ServerMsg {
-    #[serde(rename = "get_tabs")]
-    GetTabs {edge : String},
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(tag = "action")]
-enum ClientMsg {
-    //THIS IS VARIANT SO { action : tabs , tabs : [...] , edge : left}
-    #[serde(rename = "tabs")]
-    Tabs { tabs: Vec<String>  , edge : String},
-
-    // AND THIS WILL BE {action : edge , tabs : [...]}
-    // #[serde(rename = "edge")]
-    // Edge { tabs: Vec<String> },
-}
-
-#[derive(Deserialize, Debug , Serialize)]
-#[serde(tag = "action")]
-enum GlobalMsg {
-    #[serde(rename = "tabs")]
-    Tabs { tabs: Vec<String> , time : String}
-}
 
 #[derive(Debug)]
 struct DeviceInfo {
     ip: String,
-    tx : mpsc::Sender<String>,
+    //config.toml entry it is registered under , None = not in config
+    slot : Option<String>,
+    tx : mpsc::Sender<GlobalMsg>,
+    //fired when a newer link of the same device takes the entry over
+    kick : Option<oneshot::Sender<()>>,
 }
+//device id ----> connected device
 type DeviceMap = Arc<Mutex<HashMap<String , DeviceInfo>>>;
+//swapped whole on every valid config.toml edit
+type LayoutRx = watch::Receiver<Arc<Layout>>;
+
+//server ---- ping ----> peer every HEARTBEAT , nothing back for DEAD_AFTER = the peer is gone
+const HEARTBEAT: Duration = Duration::from_secs(10);
+const DEAD_AFTER: Duration = Duration::from_secs(30);
+
+//what every connection needs , cloned per peer
+#[derive(Clone)]
+struct Shared {
+    device_map : DeviceMap,
+    layout : LayoutRx,
+    //tabs that come in ---- local_channel ----> chrome_ext
+    extensions : Extensions,
+    browser : Option<Arc<dyn BrowserLauncher>>,
+    outbox : Option<Outbox>,
+    //what may be opened here
+    policy : Arc<UrlPolicy>,
+    //`--pair` , the only time a new device is accepted
+    pairing : Arc<PairWindow>,
+    //answers per device id , outlive the link so a resend after a reconnect isn't opened twice
+    replies : PeerReplies,
+}
 #[tokio::main]
 async fn main() {
 
-    let (local_tx, _) = broadcast::channel::<String>(16);
-    let (global_tx, _) = broadcast::channel::<String>(16);
+    //--config <path> works for both the server and check-config
+    let args: Vec<String> = env::args().skip(1).collect();
+    let config_path = config::config_path(&args);
+
+    //`chrome_leap-server check-config` validates and exits , nothing is bound
+    if config::subcommand(&args) == Some("check-config") {
+        std::process::exit(config::check_config(&config_path));
+    }
+
+    let pairing = PairWindow::from_args(&args).unwrap_or_else(|e| {
+        eprintln!("[PAIR] {}" , e);
+        std::process::exit(1);
+    });
+    match pairing.describe() {
+        Some(open) => println!("[PAIR] pairing {}" , open),
+        None => println!("[PAIR] pairing is closed , restart with --pair to add a device"),
+    }
+
+    let (local_tx, _) = broadcast::channel::<LocalEvent>(16);
 
     let _keep_local_alive = local_tx.clone();
-    let _keep_global_alive = global_tx.clone();
 
     let device_map  : DeviceMap = Arc::new(Mutex::new(HashMap::new()));
 
-    let screen_config =  load_config().unwrap_or_else(|e| {
-        eprintln!("[CONFIG] Config load error : {}" , e);
+    let content = std::fs::read_to_string(&config_path).unwrap_or_else(|e| {
+        eprintln!("[CONFIG] Config load error ({}) : {}" , config_path.display() , e);
         std::process::exit(1);
     });
-    let screen_config_map = build_map(&screen_config);
+    let (screen_config , layout) = config::validate(&content).unwrap_or_else(|e| {
+        eprintln!("[CONFIG] {}:{}" , config_path.display() , e);
+        std::process::exit(1);
+    });
+    println!("[CONFIG] using {}" , config_path.display());
+    log::set_level(screen_config.log.level);
+    let (layout_tx , layout) = watch::channel(Arc::new(layout));
+
+    //[outbox] , tabs for a device that is asleep wait on disk until it is back
+    let outbox = Outbox::new(&screen_config.outbox);
+    if outbox.is_some() {
+        println!(
+            "[OUTBOX] holding tabs for offline devices in {} ( {} s , {} per device )" ,
+            screen_config.outbox.dir.display() , screen_config.outbox.ttl_secs , screen_config.outbox.max_per_device
+        );
+    }
 
-    // [edge_checker] ----- local_channel ----> ws 
+    //config.toml ---- [watch] ----> layout , connected peers are re-registered against the new one
     {
-        let local_tx_clone = local_tx.clone();
-        edge_check(move |edge| {
+        let device_map = device_map.clone();
+        let config_path = config_path.clone();
+        let outbox = outbox.clone();
+        let mut config_watch = ConfigWatch::new(&config_path , content);
+        //what the listeners / edge checker were started with
+        let running = screen_config.clone();
+
+        println!("[CONFIG] watching {} , [[devices]] and [log] reload live , other sections ( listen , urls ... ) need a restart" , config_path.display());
+        tokio::spawn(async move {
+            loop {
+                let (config , next) = config_watch.next().await;
+                //the listeners , url policy and launcher were built from `running` , only a restart rebuilds them
+                for section in running.needs_restart(&config) {
+                    println!("[CONFIG] [{}] changed , that needs a restart , the server keeps using the old [{}]" , section , section);
+                }
+                log::set_level(config.log.level);
 
-            // edge_checker ----- [local_channel] ----> ws 
-            match edge {
-                Edge::Left => {
-                    let _  = local_tx_clone.send("get_tabs-left".to_string());
+                let changes = layout_tx.borrow().diff(&next);
+                println!("[CONFIG] reloaded {} ({} changes)" , config_path.display() , changes.len());
+                for change in changes {
+                    println!("[CONFIG]   {}" , change);
                 }
-                Edge::Right => {
-                    let _ = local_tx_clone.send("get_tabs-right".to_string());
+
+                let mut map_guard = device_map.lock().await;
+                for (id , device) in map_guard.iter_mut() {
+                    let slot = next.identify(id , &device.ip).map(str::to_string);
+                    if slot != device.slot {
+                        println!("[CONFIG] '{}' now registered as {:?} (was {:?})" , id , slot , device.slot);
+                        device.slot = slot;
+
+                        //a connected device that just got a slot picks up what was held for it
+                        if let Some(slot) = &device.slot {
+                            for msg in take_outbox(outbox.as_ref() , slot).await {
+                                if let Err(e) = device.tx.try_send(msg) {
+                                    let msg = match e {
+                                        mpsc::error::TrySendError::Full(msg) | mpsc::error::TrySendError::Closed(msg) => msg,
+                                    };
+                                    if let Some(outbox) = &outbox {
+                                        let _ = outbox.push(slot , msg).await;
+                                    }
+                                }
+                            }
+                        }
+                    }
                 }
+                layout_tx.send_replace(Arc::new(next));
             }
         });
     }
 
-    //websocket listner
-    //[ws] ---- global_boardcast ---- TCP ----> another computer 
-    {
-        let local_tx_clone = local_tx.clone();
-        let device_map_for_ws = device_map.clone();
-
-        tokio::spawn( async move {
-            let url = "0.0.0.0:24810";
-            let listener: TcpListener = TcpListener::bind(url).await.expect("[WS] can't bind with this addr");
+    // [edge_checker] ----- local_channel ----> ws 
+    //chrome_ext registers here , the edge checker asks it who owns the front window
+    let registry = Registry::default();
+    let extensions = Extensions::new(local_tx.clone() , registry);
 
-            println!("[WS] listening @ {}" , url);
+    {
+        let extensions = extensions.clone();
+        edge_check(screen_config.edge.to_core() , move |event| {
 
-            while let Ok((stream , peer_addr)) = listener.accept().await {
-                println!("[WS] accept conn from {}" , peer_addr);
-                tokio::spawn(handle_ws(stream , peer_addr , local_tx_clone.clone(), device_map_for_ws.clone()));
-            }
+            // edge_checker ----- [local_channel] ----> ws
+            extensions.get_tabs(event);
         });
     }
 
+    //websocket listner
+    //chrome_ext ---- ws ----> [forwarder] ---- tabs_channel ----> router
+    let (tabs_tx , mut tabs_rx) = mpsc::channel::<ClientMsg>(32);
+    tokio::spawn(local_ws::listen(screen_config.listen.local , extensions.clone() , tabs_tx , screen_config.listen.ws_guard() , screen_config.urls.to_policy()));
 
-    // tcp (global)
+    //router
+    //recv chrome_ext ---- ws ----> forwarder ---- [private_channel] ----- tcp ----> another_computer
+    //convert edge --> layout --> tcp specific stream
     {
-        let global_tx_clone = global_tx.clone();
-        let device_map_clone = device_map.clone();
-        let screen_config_map_clone = screen_config_map.clone();
+        let device_map = device_map.clone();
+        let layout = layout.clone();
+        let outbox = outbox.clone();
 
         tokio::spawn(async move {
-            let url: &str = "0.0.0.0:24811";
-            let listener: TcpListener = TcpListener::bind(url).await.expect("[tcp] can't bind with this addr");
-            println!("[TCP] listening @ {}" , url);
-
-            while let Ok((mut stream , addr)) = listener.accept().await {
-
-                println!("[TCP] connection from : {}" , addr);
-                let ip = addr.ip().to_string();
-
-                //channel
-                let mut global_recv = global_tx_clone.subscribe();
-                let (tx , mut rx) = mpsc::channel::<String>(32);
-
-                //compare with config set edge accordingly
-                if let Some(edge) = screen_config_map_clone.get(&ip) {
-                    println!("[CONFIG] registor from : {}" , addr);
-                    device_map_clone.lock().await.insert(
-                        edge.clone(),
-                        DeviceInfo { ip: ip.clone() , tx},
-                    );
-                }
-                
-                tokio::spawn(async move {
-                    let mut buf = vec![0u8; 1024];
-                    loop {
-                        tokio::select! {
-                            //ws ---- [global_boardcast] ---- TCP ----> another computer 
-                            Ok(msg) = global_recv.recv() => {
-
-                                // ws --- global boardcast ---- [TCP] -----> another computer
-                                if let Err(e) = stream.write_all(msg.as_bytes()).await {
-                                    println!("[TCP] forwarding fail @ global channel : {}" , e);
-                                    break;
+            while let Some(msg) = tabs_rx.recv().await {
+                match msg {
+                    ClientMsg::Tabs {tabs , edge , window , ..} => {
+                        let Some(hit) = EdgeHit::from_token(&edge) else {
+                            println!("[TCP][CONFIG] chrome_ext answered with unknown edge : '{}'" , edge);
+                            continue;
+                        };
+                        let current = layout.borrow().clone();
+                        let Some(id) = current.resolve(&hit) else {
+                            println!("[TCP][CONFIG] no device configured for {} @ {:.3}" , hit.edge.as_str() , hit.at);
+                            continue;
+                        };
+
+                        let msg = GlobalMsg::Tabs { id : delivery::next_id() , tabs , time : time_now() , window };
+                        //never wait on a device channel under the map lock , its handle_peer needs the lock to tear down
+                        let map_guard = device_map.lock().await;
+                        let undelivered = match map_guard.values().find(|d| d.slot.as_deref() == Some(id)) {
+                            Some(device) => match device.tx.try_send(msg) {
+                                Ok(()) => None,
+                                Err(e) => {
+                                    println!("[TCP][GLOBAL_CHANNEL] Fail to send '{}' ({}) , edge : {}, Err : {}" , id , device.ip , edge , e);
+                                    match e {
+                                        mpsc::error::TrySendError::Full(msg) | mpsc::error::TrySendError::Closed(msg) => Some(msg),
+                                    }
                                 }
+                            },
+                            None => {
+                                println!("[TCP][CONFIG] Target device '{}' for edge : '{}' is not connected" , id , edge );
+                                Some(msg)
                             }
+                        };
 
-                            //use some cause it cannot fail
-                            //recv chrome_ext ---- ws ----> forwarder ---- private_channel ----- [tcp] ----> another_computer 
-                            Some(msg) = rx.recv() => {
-
-                                
-                                if let Err(e) = stream.write_all(msg.as_bytes()).await {
-                                    print!("[TCP] forwarding fail @ private channel : {}" , e);
-                                    break;
-                                }
+                        //still under the map lock , so a device registering right now can't miss it
+                        if let (Some(msg) , Some(outbox)) = (undelivered , &outbox) {
+                            match outbox.push(id , msg).await {
+                                Ok(waiting) => println!("[OUTBOX] holding tabs for '{}' until it connects ( {} waiting )" , id , waiting),
+                                Err(e) => println!("[OUTBOX] can't hold tabs for '{}' : {:#}" , id , e),
                             }
+                        }
+                    }
+                    //local_ws keeps these for itself
+                    ClientMsg::Register { .. } | ClientMsg::Opened { .. } => {}
+                }
+            }
+        });
+    }
 
-                            //another computer ---- [TCP] ----> local computer
-                            result = stream.read(&mut buf) => {
-                                match result {
-                                    Ok(0) => {
-                                        println!("[TCP] peer disconnect ; {}" , addr);
-                                        break;
-                                    }
-                                    Ok(n) => {
-                                        let msg_str = String::from_utf8_lossy(&buf[..n]);
-                                        println!("[TCP] Received via TCP : {}" , msg_str);
-                                    }
 
-                                    Err(e) => {
-                                        println!("[TCP] receive msg err : {}" , e);
-                                        break;
-                                    }
+    //another computer ---- tabs ----> [launcher] , picked once , a missing browser only matters when tabs arrive
+    let browser : Option<Arc<dyn BrowserLauncher>> = match screen_config.browser.launcher() {
+        Ok(launcher) => {
+            println!("[BROWSER] tabs open with {}" , launcher.name());
+            Some(Arc::from(launcher))
+        }
+        Err(e) => {
+            println!("[BROWSER] {} , incoming tabs will be dropped" , e);
+            None
+        }
+    };
+
+    // tcp / quic (global)
+    {
+        let shared = Shared {
+            device_map : device_map.clone(),
+            layout : layout.clone(),
+            extensions,
+            browser,
+            outbox,
+            policy : Arc::new(screen_config.urls.to_policy()),
+            pairing : Arc::new(pairing),
+            replies : PeerReplies::default(),
+        };
+        let url = screen_config.listen.global;
+
+        match screen_config.transport {
+            Transport::Tcp => {
+                tokio::spawn(async move {
+                    let listener: TcpListener = TcpListener::bind(url).await.expect("[tcp] can't bind with this addr");
+                    println!("[TCP] listening @ {}" , url);
+                    let _mdns = announce(url.port() , Transport::Tcp , None);
+
+                    while let Ok((stream , addr)) = listener.accept().await {
+                        println!("[TCP] connection from : {}" , addr);
+                        tokio::spawn(handle_peer(Box::new(stream) , addr , shared.clone()));
+                    }
+                });
+            }
+
+            Transport::Quic => {
+                let (endpoint , fingerprint) = quic::endpoint(url , Path::new(quic::CERT_DIR)).unwrap_or_else(|e| {
+                    eprintln!("[QUIC] can't start endpoint : {}" , e);
+                    std::process::exit(1);
+                });
+                println!("[QUIC] listening @ {} (udp)" , url);
+                println!("[QUIC] certificate fingerprint , pin this on the client : {}" , fingerprint);
+                let mdns = announce(url.port() , Transport::Quic , Some(&fingerprint));
+
+                tokio::spawn(async move {
+                    let _mdns = mdns;
+                    while let Some(incoming) = endpoint.accept().await {
+                        let shared = shared.clone();
+
+                        tokio::spawn(async move {
+                            let conn = match incoming.await {
+                                Ok(conn) => conn,
+                                Err(e) => {
+                                    println!("[QUIC] connection failed : {}" , e);
+                                    return;
                                 }
-                            }
-                        }
+                            };
+                            let addr = conn.remote_address();
+                            println!("[QUIC] connection from : {}" , addr);
+
+                            //the client opens one bi stream and says hello on it
+                            let streams = match conn.accept_bi().await {
+                                Ok(streams) => streams,
+                                Err(e) => {
+                                    println!("[QUIC] no stream from {} : {}" , addr , e);
+                                    return;
+                                }
+                            };
+
+                            let link = QuicLink::new(conn , streams , None);
+                            handle_peer(Box::new(link) , addr , shared).await;
+                        });
                     }
                 });
             }
-        });
-    }    
+        }
+    }
 
     loop {
         sleep(Duration::from_secs(60)).await;
     }
     
-} 
+}
 
-async fn handle_ws(
-    stream : TcpStream, 
-    peer_addr : std::net::SocketAddr, 
-    local_tx : broadcast::Sender<String>, 
-    device_map : DeviceMap,
+//lan ----> [_chromeleap._tcp] so clients find us without PORT
+//not fatal , clients can still be pointed at the ip by hand
+fn announce(port : u16 , transport : Transport , fingerprint : Option<&str>) -> Option<discovery::ServiceDaemon> {
+    match discovery::advertise(&handshake::local_hostname() , port , transport , fingerprint) {
+        Ok(daemon) => {
+            println!("[MDNS] advertising as '{}'" , handshake::local_hostname());
+            Some(daemon)
+        }
+        Err(e) => {
+            println!("[MDNS] discovery disabled : {}" , e);
+            None
+        }
+    }
+}
+
+//one connected machine , whatever transport it came in on
+async fn handle_peer(
+    link : BoxLink,
+    addr : SocketAddr,
+    shared : Shared,
     ) {
 
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
+    let Shared { device_map , layout , extensions , browser , outbox , policy , pairing , replies } = shared;
+
+    let ip = addr.ip().to_string();
+
+    //channel
+    let (tx , mut rx) = mpsc::channel::<GlobalMsg>(32);
+    let (kick_tx , mut kick_rx) = oneshot::channel::<()>();
+
+    let mut stream = BufReader::new(link);
+
+    //another computer ---- [hello] ----> local computer
+    let peer = match handshake::accept(&mut stream).await {
+        Ok(peer) => peer,
         Err(e) => {
-            println!("[WS][handle_ws] handshake err : {} (from {})" , e , peer_addr);
+            println!("[TCP][HANDSHAKE] refused {} : {}" , addr , e);
             return;
         }
     };
-    let (mut ws_sender , mut ws_reciver) = ws_stream.split();
-    let mut local_recv = local_tx.subscribe();
+    println!("[TCP][HANDSHAKE] {} is '{}' on '{}' (protocol v{} , caps {:?})" , addr , peer.id() , peer.hostname , peer.version , peer.capabilities);
+
+    //another computer ---- [auth / pair] ----> local computer
+    let mut session = match handshake::authenticate(&mut stream , &peer , &pairing).await {
+        Ok(AuthOutcome::Session(session)) => *session,
+        Ok(AuthOutcome::Paired) => return,
+        Err(e) => {
+            println!("[TCP][AUTH] refused {} ('{}') : {}" , addr , peer.id() , e);
+            return;
+        }
+    };
+    println!("[TCP][AUTH] '{}' authenticated" , peer.id());
+
+    //compare with config , every configured device gets its own entry
+    //map locked first so a reload can't swap the layout in between
+    let waiting = {
+        let mut map_guard = device_map.lock().await;
+        let current = layout.borrow().clone();
+        let slot = current.identify(peer.id() , &ip);
+        match slot {
+            Some(slot) => {
+                if let Some(hint) = current.ip_hint(slot).filter(|hint| *hint != ip) {
+                    println!("[CONFIG] '{}' moved from {} to {} , still the same device" , slot , hint , ip);
+                }
+                println!("[CONFIG] registor '{}' from : {}" , slot , addr);
+            }
+            None => println!("[CONFIG] '{}' ({}) is not in config.toml , it can send but won't receive tabs until it is" , peer.id() , addr),
+        }
+        let entry = DeviceInfo { ip: ip.clone() , slot : slot.map(str::to_string) , tx : tx.clone() , kick : Some(kick_tx)};
+
+        //newest link wins , the old one is probably half open after a sleep or a network change
+        if let Some(mut old) = map_guard.insert(peer.id().to_string() , entry) {
+            println!("[TCP] '{}' reconnected from {} , closing its old link from {}" , peer.id() , addr , old.ip);
+            if let Some(kick) = old.kick.take() {
+                let _ = kick.send(());
+            }
+        }
+
+        //taken under the lock , the router queues under it too so nothing falls in between
+        match slot {
+            Some(slot) => take_outbox(outbox.as_ref() , slot).await,
+            None => Vec::new(),
+        }
+    };
+
+    //outbox ---- private_channel ----> this link , a task so a long queue can't block on our own channel
+    if !waiting.is_empty() {
+        println!("[OUTBOX] '{}' is back , sending {} held throws" , peer.id() , waiting.len());
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            for msg in waiting {
+                if tx.send(msg).await.is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    let (reader , mut writer) = tokio::io::split(stream);
+    let mut frames = FrameReader::new(reader);
+
+    //tabs we sent and wait an answer for , answers we gave live in `replies` across links
+    let mut pending = Pending::new(Retry::default());
+
+    let mut heartbeat = tokio::time::interval(HEARTBEAT);
+    let mut last_seen = Instant::now();
+
+    'link: loop {
+        //nothing pending = a timer that never fires
+        let due = pending.next_due().map(Instant::from_std).unwrap_or_else(|| Instant::now() + Duration::from_secs(3600));
 
-    loop {
         tokio::select! {
+            //a newer link of the same device took over
+            _ = &mut kick_rx => {
+                println!("[TCP] closing the old link of '{}' from {}" , peer.id() , addr);
+                break;
+            }
 
-            //why use - instead of json welll.... i don't know i expect this to be faster ;)
-            //TODO - find evident to back this up ¯\(ツ)/¯
+            //local computer ---- [ping] ----> another computer , and drop a peer that went quiet
+            _ = heartbeat.tick() => {
+                if last_seen.elapsed() >= DEAD_AFTER {
+                    println!("[TCP] '{}' ({}) missed heartbeats for {}s , dropping it" , peer.id() , addr , DEAD_AFTER.as_secs());
+                    break;
+                }
+                let ping = serde_json::to_string(&GlobalMsg::Ping).unwrap_or_default();
+                if let Err(e) = write_frame(&mut writer , &session.seal(&ping)).await {
+                    println!("[TCP] can't ping '{}' : {}" , peer.id() , e);
+                    break;
+                }
+            }
 
-            //edge checker ----- [local_channel] -----> forwarder --- ws ---> chrome_ext
-            Ok(msg) = local_recv.recv() => {
-                if let Some(msg_type) = msg.split('-').next() {
-                    if msg_type == "get_tabs" {
+            //use some cause it cannot fail
+            //recv chrome_ext ---- ws ----> forwarder ---- private_channel ----- [tcp] ----> another_computer
+            Some(msg) = rx.recv() => {
 
-                        //edge checker ----- local_channel -----> [forwarder] --- ws ---> chrome_ext
-                        let edge = msg.split('-').nth(1).unwrap_or("");
-                        match serde_json::to_string(&ServerMsg::GetTabs {edge : edge.to_string()}) {
-                            Ok(json_msg) => {
-                                println!("[WS] send to get_tabs to {}" , peer_addr);
+                //checked before sealing , one throw too big for a frame is dropped , the link stays
+                let json = match delivery::frame_json(&msg) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        println!("[TCP] not sending {:?} to '{}' : {}" , msg.id() , peer.id() , e);
+                        continue;
+                    }
+                };
+                if let Err(e) = write_frame(&mut writer , &session.seal(&json)).await {
+                    print!("[TCP] forwarding fail @ private channel : {}" , e);
+                    break;
+                }
+                if let GlobalMsg::Tabs { id , tabs , .. } = &msg {
+                    pending.track(*id , json , tabs.len() , std::time::Instant::now());
+                }
+            }
 
-                                //edge checker ----- local_channel -----> forwarder --- [ws] ---> chrome_ext
-                                if let Err(e) = ws_sender.send(Message::Text(json_msg)).await {
-                                    eprintln!("[WS] fail to send msg to {} , err : {}" , peer_addr , e);
-                                    break; //ws prob disconnect so we break
-                                }
-                            }
-                            Err(e) => {
-                                eprintln!("[serde_json] fail to convert to json {} , err : {}" , msg , e );
-                                continue;
+            //no ack in time ---- [TCP] ----> another computer , same line again
+            _ = sleep_until(due) => {
+                for due in pending.poll(std::time::Instant::now()) {
+                    match due {
+                        Due::Resend { id , line , attempt } => {
+                            println!("[TCP] no answer from '{}' for tabs {} , sending again (try {})" , peer.id() , id , attempt);
+                            if let Err(e) = write_frame(&mut writer , &session.seal(&line)).await {
+                                println!("[TCP] retry fail @ private channel : {}" , e);
+                                break 'link;
                             }
                         }
-
+                        Due::GaveUp { id , tabs , attempts } => {
+                            println!("[TCP] '{}' never answered for {} tabs ( id {} , {} tries ) , giving up" , peer.id() , tabs , id , attempts);
+                        }
                     }
                 }
             }
 
-            //recv chrome_ext ---- [ws] ----> forwarder ----- private_channel ---- tcp ----> another_computer 
-            Some(msg) = ws_reciver.next() => {
-
-                println!("[WS] forwarding from perr {}" , peer_addr);
-                let msg = match msg {
-                    Ok(msg) => msg,
-                    Err(e) => {
-                        println!("[WS] Error reciving msg err : {} ,  from {} " , e , peer_addr);
+            //another computer ---- [TCP] ----> local computer ---- chrome
+            result = frames.next_frame() => {
+                match result {
+                    Ok(None) => {
+                        println!("[TCP] peer disconnect ; {}" , addr);
                         break;
                     }
-                };
+                    Ok(Some(line)) => {
+                        last_seen = Instant::now();
+                        let line = match session.open(&line) {
+                            Ok(line) => line,
+                            Err(e) => {
+                                println!("[TCP][AUTH] dropping {} , bad line : {}" , addr , e);
+                                break;
+                            }
+                        };
+
+                        match serde_json::from_str::<GlobalMsg>(&line) {
+                            Ok(GlobalMsg::Tabs { id, tabs, time, window }) => {
+                                chrome_leap_core::debug!("[TCP] tabs {} from '{}' sent @ {}" , id , peer.id() , time);
+
+                                //a retry of something already opened gets the old answer , not a second window
+                                //the client sends unanswered throws again on its next link , same ids
+                                let count = tabs.len();
+                                let (reply , repeat) = replies.answer(peer.id() , id , || delivery::open(&extensions , browser.as_deref() , &policy , id , tabs , window)).await;
+                                if repeat {
+                                    println!("[TCP] '{}' sent tabs {} again , answering again" , peer.id() , id);
+                                } else {
+                                    println!("[TCP] {} tabs from '{}'" , count , peer.id());
+                                }
 
-                //recv chrome_ext ---- ws ----> [forwarder] ----  private_channel ----- tcp ----> another_computer
-                //convert edge --> tcp specific stream 
-                if let Message::Text(text) = msg {
-                    match serde_json::from_str::<ClientMsg>(&text) {
-                        Ok(ClientMsg::Tabs {tabs , edge}) => {
-                            let now = SystemTime::now()
-                                .duration_since(UNIX_EPOCH)
-                                .unwrap()
-                                .as_nanos()// timestamp ms
-                                .to_string();
-                            match serde_json::to_string(&GlobalMsg::Tabs { tabs : tabs , time : now}) {
-                                Ok(json) => {
-                                    let map_guard = device_map.lock().await;
-                                    if let Some(device) = map_guard.get(&edge) {
-                                        //recv chrome_ext ---- ws ----> forwarder ---- [private_channel] ----- tcp ----> another_computer 
-                                        if let Err(e) = device.tx.send(json + "\n").await {
-                                            println!("[TCP][GLOBAL_CHANNEL] Fail to send ip : {} , edge : {}, Err : {}" , device.ip , edge , e);
+                                //local computer ---- [ack / nack] ----> another computer
+                                match serde_json::to_string(&reply) {
+                                    Ok(json) => {
+                                        if let Err(e) = write_frame(&mut writer , &session.seal(&json)).await {
+                                            println!("[TCP] can't answer '{}' : {}" , peer.id() , e);
+                                            break;
                                         }
-
-                                    } else {
-                                        println!("[TCP][CONFIG] Target device with edge : '{}' not found in " ,edge );
                                     }
+                                    Err(e) => println!("[TCP][JSON] serialize error: {}", e),
                                 }
-                                Err(e) => {
-                                    println!("[TCP][JSON] serialize error: {}", e);
+                            }
+                            //heartbeat , answered right away so the client doesn't think we're gone
+                            Ok(GlobalMsg::Ping) => {
+                                let pong = serde_json::to_string(&GlobalMsg::Pong).unwrap_or_default();
+                                if let Err(e) = write_frame(&mut writer , &session.seal(&pong)).await {
+                                    println!("[TCP] can't answer ping from '{}' : {}" , peer.id() , e);
+                                    break;
                                 }
                             }
-
-                            
-                        }
-                        Err(e) => {
-                            println!("[serde json] Failed to parse JSON from {}: {}", peer_addr, e);
-                            println!("[serde json] Raw message was: {}", text);
+                            Ok(GlobalMsg::Pong) => {}
+                            Ok(answer) => match pending.answer(&answer , std::time::Instant::now()) {
+                                Some(outcome) => println!("[TCP] '{}' {}" , peer.id() , outcome),
+                                None => chrome_leap_core::debug!("[TCP] late or unknown answer from '{}' : {:?}" , peer.id() , answer),
+                            },
+                            Err(e) => println!("[TCP] Decode json Err from {} : {}" , addr , e),
                         }
                     }
-                } else if msg.is_close() {
-                    println!("[WS] {} disconnected.", peer_addr);
-                    break;
+
+                    Err(e) => {
+                        println!("[TCP] receive msg err : {:#}" , e);
+                        break;
+                    }
                 }
             }
-
         }
     }
-    
-}
 
-//==== handle config =====
-#[derive(Debug, Deserialize)]
-struct Config {
-    devices: Vec<Device>
-}
-#[derive(Debug, Deserialize)]
-struct Device {
-    ip : String,
-    edge: String,
-}
-
-fn load_config() -> anyhow::Result<Config> {
-    let content = fs::read_to_string("config.toml")?;
-    let config : Config = toml::from_str(&content)?; //if Err -return
-    Ok(config) 
-}
-fn build_map(config : &Config) -> HashMap<String , String> {
-    let mut map = HashMap::new();
+    //link gone , unregister it unless a newer link already took the entry
+    //closed under the map lock so the router either got its throw in before or sends to whoever is registered now
+    let mut map_guard = device_map.lock().await;
+    rx.close();
+    let mut left: Vec<GlobalMsg> = pending.unanswered().iter().filter_map(|line| serde_json::from_str(line).ok()).collect();
+    while let Ok(msg) = rx.try_recv() {
+        left.push(msg);
+    }
 
-    for device in &config.devices {
-        map.insert(device.ip.clone(), device.edge.clone());
+    let ours = map_guard.get(peer.id()).is_some_and(|d| d.tx.same_channel(&tx));
+    if ours {
+        let slot = map_guard.remove(peer.id()).and_then(|d| d.slot);
+        println!("[TCP] '{}' ({}) unregistered" , peer.id() , addr);
+        if !left.is_empty() {
+            match (&outbox , &slot) {
+                (Some(outbox) , Some(slot)) => {
+                    for msg in left {
+                        if let Err(e) = outbox.push(slot , msg).await {
+                            println!("[OUTBOX] can't hold tabs for '{}' : {:#}" , slot , e);
+                        }
+                    }
+                    println!("[OUTBOX] '{}' left with unanswered tabs , holding them until it is back" , slot);
+                }
+                _ => println!("[TCP] '{}' left , {} throws were never answered" , peer.id() , left.len()),
+            }
+        }
+    } else if let Some(newer) = map_guard.get(peer.id()) {
+        //same ids , the device answers a repeat without opening twice
+        for msg in left {
+            if newer.tx.try_send(msg).is_err() {
+                println!("[TCP] '{}' new link is busy , a throw from the old link is lost" , peer.id());
+            }
+        }
     }
+}
 
-    map
-}
\ No newline at end of file
+//everything held for `slot` , errors only cost the held tabs
+async fn take_outbox(outbox : Option<&Outbox> , slot : &str) -> Vec<GlobalMsg> {
+    let Some(outbox) = outbox else {
+        return Vec::new();
+    };
+    outbox.take(slot).await.unwrap_or_else(|e| {
+        println!("[OUTBOX] can't read held tabs for '{}' : {:#}" , slot , e);
+        Vec::new()
+    })
+}
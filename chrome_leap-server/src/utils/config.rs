@@ -0,0 +1,512 @@
+use std::net::SocketAddr;
+use std::num::{NonZeroU32, NonZeroU64};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use chrome_leap_core::browser::{self, BrowserLauncher};
+use chrome_leap_core::local_ws::WsGuard;
+use chrome_leap_core::log::Level;
+use chrome_leap_core::os_check::EdgeConfig;
+use chrome_leap_core::transport::Transport;
+use chrome_leap_core::url_policy::{Pattern, UrlPolicy};
+
+use crate::utils::layout::Layout;
+
+//next to the binary , what older setups use
+pub const CONFIG_PATH: &str = "config.toml";
+
+//==== handle config =====
+//unknown keys are errors so a typo doesn't silently fall back to a default
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    //tcp ( default ) or quic , the client has to use the same one
+    #[serde(default)]
+    pub transport: Transport,
+    #[serde(default)]
+    pub listen: Listen,
+    #[serde(default)]
+    pub edge: EdgeSettings,
+    #[serde(default)]
+    pub browser: Browser,
+    #[serde(default)]
+    pub log: Log,
+    #[serde(default)]
+    pub outbox: OutboxSettings,
+    #[serde(default)]
+    pub urls: Urls,
+    #[serde(default)]
+    pub devices: Vec<Spanned<Device>>,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Listen {
+    //chrome_ext ---- ws ----> here , loopback so nothing else on the lan can throw tabs
+    pub local: SocketAddr,
+    //another computer ---- tcp / quic ----> here
+    pub global: SocketAddr,
+    //chrome://extensions ids allowed to connect , empty = none , the ws refuses every extension
+    pub extension_ids: Vec<String>,
+    //the extension has to connect with ?token=<this> , none = no token
+    pub token: Option<String>,
+}
+
+impl Default for Listen {
+    fn default() -> Self {
+        Listen {
+            local: SocketAddr::from(([127, 0, 0, 1], 24810)),
+            global: SocketAddr::from(([0, 0, 0, 0], 24811)),
+            extension_ids: Vec::new(),
+            token: None,
+        }
+    }
+}
+
+impl Listen {
+    pub fn ws_guard(&self) -> WsGuard {
+        WsGuard {
+            extension_ids: self.extension_ids.clone(),
+            token: self.token.clone().filter(|t| !t.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EdgeSettings {
+    pub band_px: NonZeroU32,
+    pub hold_ms: u64,
+    pub cooldown_ms: u64,
+}
+
+impl Default for EdgeSettings {
+    fn default() -> Self {
+        let core = EdgeConfig::default();
+        EdgeSettings {
+            band_px: NonZeroU32::new(core.band_px as u32).unwrap_or(NonZeroU32::MIN),
+            hold_ms: core.hold.as_millis() as u64,
+            cooldown_ms: core.cooldown.as_millis() as u64,
+        }
+    }
+}
+
+impl EdgeSettings {
+    pub fn to_core(&self) -> EdgeConfig {
+        EdgeConfig {
+            band_px: self.band_px.get() as f64,
+            hold: Duration::from_millis(self.hold_ms),
+            cooldown: Duration::from_millis(self.cooldown_ms),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Browser {
+    //auto ( default ) , chrome , chromium , brave , edge , firefox , xdg-open
+    pub name: Option<String>,
+    //e.g. "flatpak run com.brave.Browser {urls}" , beats name , {url} = one process per tab , urls go last without either
+    pub command: Option<String>,
+}
+
+impl Browser {
+    pub fn launcher(&self) -> anyhow::Result<Box<dyn BrowserLauncher>> {
+        browser::select(self.name.as_deref() , self.command.as_deref())
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Log {
+    //info ( default ) or debug
+    pub level: Level,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutboxSettings {
+    //off ( default ) = tabs for a device that isn't connected are dropped
+    pub enabled: bool,
+    //one file per device in here
+    pub dir: PathBuf,
+    //older throws are dropped instead of delivered , 0 would drop all of them so it's refused ( enabled = false is off )
+    pub ttl_secs: NonZeroU64,
+    //oldest goes first when a device has more waiting
+    pub max_per_device: usize,
+}
+
+impl Default for OutboxSettings {
+    fn default() -> Self {
+        OutboxSettings {
+            enabled: false,
+            dir: PathBuf::from("chrome_leap-outbox"),
+            ttl_secs: NonZeroU64::new(24 * 60 * 60).expect("a day isn't 0"),
+            max_per_device: 20,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Urls {
+    //what a url may start with , http and https by default
+    pub schemes: Vec<String>,
+    //"example.com" covers its subdomains too , empty allow = any domain
+    pub allow_domains: Vec<String>,
+    pub deny_domains: Vec<String>,
+    //regexes over the url , case insensitive , empty allow = any url , a bad one is an error at its line
+    pub allow_patterns: Vec<Pattern>,
+    pub deny_patterns: Vec<Pattern>,
+}
+
+impl Default for Urls {
+    fn default() -> Self {
+        let core = UrlPolicy::default();
+        Urls {
+            schemes: core.schemes,
+            allow_domains: core.allow_domains,
+            deny_domains: core.deny_domains,
+            allow_patterns: core.allow_patterns,
+            deny_patterns: core.deny_patterns,
+        }
+    }
+}
+
+impl Urls {
+    pub fn to_policy(&self) -> UrlPolicy {
+        UrlPolicy {
+            schemes: self.schemes.clone(),
+            allow_domains: self.allow_domains.clone(),
+            deny_domains: self.deny_domains.clone(),
+            allow_patterns: self.allow_patterns.clone(),
+            deny_patterns: self.deny_patterns.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Device {
+    //device id the client sends in its hello ( its hostname unless DEVICE_ID is set )
+    #[serde(default)]
+    pub id : Option<String>,
+    //only a hint when id is set , the only match when it isn't ( old configs )
+    #[serde(default)]
+    pub ip : Option<String>,
+    pub edge: String,
+
+    //part of the edge this device owns ( 0.0 - 1.0 ) , whole edge when left out
+    #[serde(default)]
+    pub from : Option<f64>,
+    #[serde(default)]
+    pub to : Option<f64>,
+}
+
+impl Device {
+    //what DeviceMap and the layout key this device by
+    pub fn key(&self) -> Option<&str> {
+        self.id.as_deref().or(self.ip.as_deref())
+    }
+}
+
+impl Config {
+    pub fn devices(&self) -> Vec<Device> {
+        self.devices.iter().map(|d| d.get_ref().clone()).collect()
+    }
+
+    //sections a running server can't pick up , a reload only swaps devices and log level
+    pub fn needs_restart(&self , next : &Config) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        if self.transport != next.transport {
+            changed.push("transport");
+        }
+        if self.listen != next.listen {
+            changed.push("listen");
+        }
+        if self.edge != next.edge {
+            changed.push("edge");
+        }
+        if self.browser != next.browser {
+            changed.push("browser");
+        }
+        if self.outbox != next.outbox {
+            changed.push("outbox");
+        }
+        if self.urls != next.urls {
+            changed.push("urls");
+        }
+        changed
+    }
+}
+
+//where in config.toml something is wrong , 1-based like an editor
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub line : usize,
+    pub column : usize,
+    pub message : String,
+}
+
+impl ConfigError {
+    fn at(content : &str , offset : usize , message : impl Into<String>) -> ConfigError {
+        let before = &content[..offset.min(content.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+        ConfigError { line , column , message : message.into() }
+    }
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self , f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f , "{}:{} : {}" , self.line , self.column , self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+//parse + every check the server would do on start
+pub fn validate(content : &str) -> Result<(Config , Layout) , ConfigError> {
+    let config : Config = toml::from_str(content).map_err(|e| {
+        let offset = e.span().map(|s| s.start).unwrap_or(0);
+        ConfigError::at(content , offset , e.message().trim_end())
+    })?;
+
+    let layout = Layout::build(&config.devices()).map_err(|e| {
+        let offset = config.devices.get(e.device).map(|d| d.span().start).unwrap_or(0);
+        ConfigError::at(content , offset , e.reason)
+    })?;
+
+    Ok((config , layout))
+}
+
+//--config <path> , then $XDG_CONFIG_HOME/chrome_leap/config.toml if it exists , then ./config.toml
+pub fn config_path(args : &[String]) -> PathBuf {
+    if let Some(path) = arg_path(args) {
+        return path;
+    }
+
+    match user_config() {
+        Some(path) if path.exists() => path,
+        _ => PathBuf::from(CONFIG_PATH),
+    }
+}
+
+//flags that take the next argument as their value , that value is never a subcommand
+const VALUE_FLAGS: [&str; 2] = ["--config", "--re-pair"];
+
+//first argument that isn't a flag or a flag's value , `--config x.toml check-config` works as well
+pub fn subcommand(args : &[String]) -> Option<&str> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            args.next();
+            continue;
+        }
+        if !arg.starts_with('-') {
+            return Some(arg);
+        }
+    }
+    None
+}
+
+fn arg_path(args : &[String]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn user_config() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            if cfg!(windows) {
+                std::env::var_os("APPDATA").map(PathBuf::from)
+            } else {
+                std::env::var_os("HOME").map(|home| Path::new(&home).join(".config"))
+            }
+        })?;
+    Some(base.join("chrome_leap").join("config.toml"))
+}
+
+//`chrome_leap-server check-config` , exit code for scripts
+pub fn check_config(path : &Path) -> i32 {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            eprintln!("[CONFIG] can't read {} : {}" , path.display() , e);
+            return 1;
+        }
+    };
+
+    match validate(&content) {
+        Ok((config , _)) => {
+            println!(
+                "[CONFIG] {} is valid : {} devices , {} transport , ws @ {} , link @ {}" ,
+                path.display() , config.devices.len() , config.transport.as_str() , config.listen.local , config.listen.global
+            );
+            match config.browser.launcher() {
+                Ok(launcher) => {
+                    println!("[CONFIG] tabs open with {}" , launcher.name());
+                    0
+                }
+                Err(e) => {
+                    eprintln!("[CONFIG] [browser] : {}" , e);
+                    1
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("{}:{}" , path.display() , e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_is_all_defaults() {
+        let (config , _) = validate("").unwrap();
+        assert_eq!(config.transport, Transport::Tcp);
+        assert_eq!(config.listen, Listen::default());
+        assert_eq!(config.edge.to_core(), EdgeConfig::default());
+        assert_eq!(config.log.level, Level::Info);
+        assert!(!config.outbox.enabled);
+        assert_eq!(config.urls.to_policy(), UrlPolicy::default());
+        assert!(config.devices.is_empty());
+    }
+
+    #[test]
+    fn full_config() {
+        let content = r#"
+transport = "quic"
+
+[listen]
+local = "127.0.0.1:24810"
+global = "0.0.0.0:4000"
+extension_ids = ["abcdefghijklmnopabcdefghijklmnop"]
+token = "s3cret"
+
+[edge]
+band_px = 4
+hold_ms = 150
+cooldown_ms = 2000
+
+[browser]
+name = "brave"
+command = "chromium --new-window"
+
+[log]
+level = "debug"
+
+[outbox]
+enabled = true
+ttl_secs = 600
+
+[urls]
+deny_domains = ["ads.example"]
+deny_patterns = ["/logout"]
+
+[[devices]]
+id = "laptop"
+edge = "left"
+"#;
+        let (config , layout) = validate(content).unwrap();
+        assert_eq!(config.listen.global.port(), 4000);
+        assert_eq!(config.listen.ws_guard(), WsGuard { extension_ids : vec!["abcdefghijklmnopabcdefghijklmnop".to_string()] , token : Some("s3cret".to_string()) });
+        assert_eq!(config.edge.to_core().hold, Duration::from_millis(150));
+        assert_eq!(config.browser.command.as_deref(), Some("chromium --new-window"));
+        assert_eq!(config.log.level, Level::Debug);
+        assert_eq!((config.outbox.enabled , config.outbox.ttl_secs.get() , config.outbox.max_per_device), (true , 600 , 20));
+        assert!(config.urls.to_policy().check("https://x.ads.example/").is_err());
+        assert!(config.urls.to_policy().check("https://a.example/logout").is_err());
+        assert_eq!(config.urls.schemes, vec!["http", "https"]);
+        assert_eq!(layout.identify("laptop", "10.0.0.2"), Some("laptop"));
+    }
+
+    #[test]
+    fn type_error_points_at_the_line() {
+        let content = "[listen]\nlocal = \"127.0.0.1:24810\"\nglobal = \"not an address\"\n";
+        let err = validate(content).unwrap_err();
+        assert_eq!((err.line , err.column), (3, 10));
+    }
+
+    #[test]
+    fn typo_is_an_error() {
+        let err = validate("[edge]\nband = 4\n").unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("band"));
+    }
+
+    #[test]
+    fn zero_band_is_rejected() {
+        assert_eq!(validate("[edge]\nband_px = 0\n").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn bad_url_pattern_points_at_the_line() {
+        let err = validate("[urls]\nallow_domains = []\ndeny_patterns = [\"/logout\", \"a(\"]\n").unwrap_err();
+        assert_eq!(err.line, 3);
+        assert!(err.message.contains("bad url pattern 'a('"));
+    }
+
+    #[test]
+    fn layout_error_points_at_the_device() {
+        let content = "[[devices]]\nid = \"a\"\nedge = \"left\"\n\n[[devices]]\nid = \"b\"\nedge = \"sideways\"\n";
+        let err = validate(content).unwrap_err();
+        assert!(err.message.contains("sideways"));
+        assert_eq!(err.line, 5);
+    }
+
+    #[test]
+    fn restart_only_for_fixed_sections() {
+        let (old , _) = validate("[[devices]]\nid = \"a\"\nedge = \"left\"\n").unwrap();
+        let (devices , _) = validate("[log]\nlevel = \"debug\"\n[[devices]]\nid = \"a\"\nedge = \"top\"\n").unwrap();
+        let (listen , _) = validate("transport = \"quic\"\n[listen]\nglobal = \"0.0.0.0:1\"\n").unwrap();
+        let (urls , _) = validate("[urls]\ndeny_domains = [\"ads.example\"]\n").unwrap();
+
+        assert!(old.needs_restart(&devices).is_empty());
+        assert_eq!(old.needs_restart(&listen), vec!["transport", "listen"]);
+        assert_eq!(old.needs_restart(&urls), vec!["urls"]);
+    }
+
+    #[test]
+    fn config_flag() {
+        let args = |a : &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(arg_path(&args(&["check-config", "--config", "/etc/cl.toml"])), Some(PathBuf::from("/etc/cl.toml")));
+        assert_eq!(arg_path(&args(&["--config=./x.toml"])), Some(PathBuf::from("./x.toml")));
+        assert_eq!(arg_path(&args(&["check-config"])), None);
+    }
+
+    #[test]
+    fn subcommand_anywhere() {
+        let args = |a : &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(subcommand(&args(&["check-config"])), Some("check-config"));
+        assert_eq!(subcommand(&args(&["--config", "/etc/cl.toml", "check-config"])), Some("check-config"));
+        assert_eq!(subcommand(&args(&["--config=x.toml", "check-config"])), Some("check-config"));
+        assert_eq!(subcommand(&args(&["--pair", "check-config"])), Some("check-config"));
+        //values of flags aren't subcommands
+        assert_eq!(subcommand(&args(&["--config", "check-config"])), None);
+        assert_eq!(subcommand(&args(&["--re-pair", "laptop"])), None);
+    }
+
+    #[test]
+    fn zero_ttl_is_refused() {
+        let err = validate("[outbox]\nenabled = true\nttl_secs = 0\n").unwrap_err();
+        assert_eq!(err.line, 3);
+    }
+}
@@ -0,0 +1,236 @@
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail};
+use tokio::io::BufReader;
+
+use chrome_leap_core::auth::{self, Pairing, Role, Session, SessionKeys, KEYS_DIR};
+use chrome_leap_core::transport::{recv_line, recv_msg, send_msg, BoxLink};
+use chrome_leap_proto::handshake::{self, HandshakeMsg, Hello, CAP_OPEN_TABS};
+use chrome_leap_proto::auth::MIN_PAIR_VERSION;
+use chrome_leap_proto::{AuthMsg, PROTOCOL_VERSION};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+//the person has to read the code here and type it over there
+const PAIR_TIMEOUT: Duration = Duration::from_secs(120);
+
+//how long `--pair` keeps pairing open after the server starts
+pub const PAIR_WINDOW: Duration = Duration::from_secs(300);
+
+//pairing is only accepted while the operator asked for it , one attempt and it closes again
+#[derive(Debug, Default)]
+pub struct PairWindow {
+    open : Mutex<Option<PairOpen>>,
+}
+
+#[derive(Debug)]
+struct PairOpen {
+    until : Instant,
+    //`--re-pair <id>` , the one paired device whose key may be replaced
+    replace : Option<String>,
+}
+
+impl PairWindow {
+    //`--pair` or `--re-pair <id>` on the command line , neither = pairing stays closed
+    pub fn from_args(args : &[String]) -> anyhow::Result<PairWindow> {
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg == "--pair" {
+                return Ok(PairWindow::open(None));
+            }
+            if arg == "--re-pair" {
+                let id = args.next().filter(|id| !id.starts_with("--")).ok_or_else(|| anyhow!("--re-pair needs the device id to pair again"))?;
+                return Ok(PairWindow::open(Some(id.clone())));
+            }
+        }
+        Ok(PairWindow::default())
+    }
+
+    fn open(replace : Option<String>) -> PairWindow {
+        PairWindow { open : Mutex::new(Some(PairOpen { until : Instant::now() + PAIR_WINDOW , replace })) }
+    }
+
+    //what the startup log says , None = closed
+    pub fn describe(&self) -> Option<String> {
+        let open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        open.as_ref().map(|open| match &open.replace {
+            Some(id) => format!("open for {}s , '{}' may replace its key" , PAIR_WINDOW.as_secs() , id),
+            None => format!("open for {}s , new devices only" , PAIR_WINDOW.as_secs()),
+        })
+    }
+
+    //takes the window for `id` and closes it whatever happens next , Ok(true) = its existing key may be replaced
+    fn claim(&self , id : &str , paired : bool , now : Instant) -> Result<bool , String> {
+        let mut open = self.open.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(window) = open.as_ref() else {
+            return Err("pairing is closed , start the server with --pair".to_string());
+        };
+        if now >= window.until {
+            *open = None;
+            return Err("the pairing window is over , start the server with --pair again".to_string());
+        }
+
+        let replace = window.replace.as_deref() == Some(id);
+        if paired && !replace {
+            return Err(format!("'{}' is already paired , start the server with --re-pair {} to replace its key" , id , id));
+        }
+
+        //one code , one try , right or wrong
+        *open = None;
+        Ok(replace)
+    }
+}
+
+pub fn local_hostname() -> String {
+    hostname::get()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+//another computer ---- [hello] ----> local computer ---- [hello_ack / hello_reject] ----> another computer
+//must run before anything else is written to the stream
+pub async fn accept(stream : &mut BufReader<BoxLink>) -> anyhow::Result<Hello> {
+
+    let line = recv_line(stream, HANDSHAKE_TIMEOUT).await.map_err(|e| anyhow!("no hello : {}", e))?;
+
+    let hello = match serde_json::from_str::<HandshakeMsg>(&line) {
+        Ok(HandshakeMsg::Hello(hello)) => hello,
+        _ => {
+            let reason = format!(
+                "expected hello as first message , peer is probably older than protocol v{}",
+                PROTOCOL_VERSION
+            );
+            let _ = send_msg(stream, &HandshakeMsg::HelloReject { version: PROTOCOL_VERSION, reason: reason.clone() }).await;
+            bail!(reason);
+        }
+    };
+
+    let reply = handshake::answer(&hello, Hello::new(local_hostname(), &[CAP_OPEN_TABS]));
+    send_msg(stream, &reply).await?;
+
+    if let HandshakeMsg::HelloReject { reason, .. } = reply {
+        bail!(reason);
+    }
+
+    Ok(hello)
+}
+
+pub enum AuthOutcome {
+    //normal connection , every line from here on goes through the session
+    Session(Box<Session>),
+    //pairing connection , done once the secret is stored
+    Paired,
+}
+
+//another computer ---- [auth / pair_request] ----> local computer
+//unknown hostnames are refused here
+pub async fn authenticate(stream : &mut BufReader<BoxLink> , peer : &Hello , pairing : &PairWindow) -> anyhow::Result<AuthOutcome> {
+
+    match recv_msg::<AuthMsg>(stream, HANDSHAKE_TIMEOUT).await? {
+        AuthMsg::Auth { nonce } => {
+            let Some(psk) = auth::load_psk(Path::new(KEYS_DIR), peer.id())? else {
+                return refuse(stream, format!("'{}' is not paired , run `chrome_leap-client pair` first", peer.id())).await;
+            };
+
+            let client_nonce = hex::decode(nonce)?;
+            let server_nonce = auth::random::<16>();
+            send_msg(stream, &AuthMsg::AuthChallenge { nonce: hex::encode(server_nonce) }).await?;
+
+            let keys = SessionKeys::derive(&psk, &client_nonce, &server_nonce);
+            let AuthMsg::AuthProof { mac } = recv_msg::<AuthMsg>(stream, HANDSHAKE_TIMEOUT).await? else {
+                return refuse(stream, "expected auth_proof".to_string()).await;
+            };
+            if !keys.check_proof(Role::Server, &hex::decode(mac)?) {
+                return refuse(stream, "wrong pairing secret".to_string()).await;
+            }
+
+            send_msg(stream, &AuthMsg::AuthOk { mac: hex::encode(keys.proof(Role::Server)) }).await?;
+            Ok(AuthOutcome::Session(Box::new(keys.session(Role::Server))))
+        }
+
+        AuthMsg::PairRequest {} => {
+            if peer.version < MIN_PAIR_VERSION {
+                return refuse(stream, format!("pairing needs protocol v{} , update the client", MIN_PAIR_VERSION)).await;
+            }
+            let paired = auth::load_psk(Path::new(KEYS_DIR), peer.id())?.is_some();
+            let replace = match pairing.claim(peer.id(), paired, Instant::now()) {
+                Ok(replace) => replace,
+                Err(reason) => return refuse(stream, reason).await,
+            };
+
+            let code = auth::pairing_code();
+            println!("[PAIR] '{}' wants to pair , type this code on it : {} {}" , peer.id() , &code[..3] , &code[3..]);
+
+            let ours = Pairing::new(Role::Server, &code, peer.id(), &local_hostname());
+            send_msg(stream, &AuthMsg::PairChallenge { pub_key: hex::encode(ours.public_key()) }).await?;
+
+            let AuthMsg::PairConfirm { pub_key, mac } = recv_msg::<AuthMsg>(stream, PAIR_TIMEOUT).await? else {
+                return refuse(stream, "expected pair_confirm".to_string()).await;
+            };
+            let keys = ours.finish(&hex::decode(pub_key)?)?;
+            if !keys.check_confirm(Role::Server, &hex::decode(mac)?) {
+                println!("[PAIR] wrong code from '{}' , pairing is closed until the server is started with --pair again" , peer.id());
+                return refuse(stream, "wrong pairing code".to_string()).await;
+            }
+
+            //keyed by device id so two clients on one host keep separate secrets
+            auth::save_psk(Path::new(KEYS_DIR), peer.id(), &keys.psk, replace)?;
+            send_msg(stream, &AuthMsg::PairOk { mac: hex::encode(keys.confirm_mac(Role::Server)) }).await?;
+            println!("[PAIR] paired with '{}'" , peer.id());
+            Ok(AuthOutcome::Paired)
+        }
+
+        other => refuse(stream, format!("expected auth or pair_request , got {:?}", other)).await,
+    }
+}
+
+async fn refuse(stream : &mut BufReader<BoxLink> , reason : String) -> anyhow::Result<AuthOutcome> {
+    let _ = send_msg(stream, &AuthMsg::AuthFail { reason: reason.clone() }).await;
+    bail!(reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list : &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn closed_unless_asked() {
+        let window = PairWindow::from_args(&args(&["--config", "x.toml"])).unwrap();
+        assert!(window.describe().is_none());
+        assert!(window.claim("laptop", false, Instant::now()).unwrap_err().contains("--pair"));
+    }
+
+    #[test]
+    fn one_attempt_per_window() {
+        let window = PairWindow::from_args(&args(&["--pair"])).unwrap();
+        assert_eq!(window.claim("laptop", false, Instant::now()), Ok(false));
+        //right or wrong , the next one needs a new --pair
+        assert!(window.claim("laptop", false, Instant::now()).is_err());
+    }
+
+    #[test]
+    fn paired_ids_need_re_pair() {
+        let window = PairWindow::from_args(&args(&["--pair"])).unwrap();
+        assert!(window.claim("laptop", true, Instant::now()).unwrap_err().contains("--re-pair laptop"));
+        //refusing it doesn't use the window up
+        assert_eq!(window.claim("desk", false, Instant::now()), Ok(false));
+
+        let window = PairWindow::from_args(&args(&["--re-pair", "laptop"])).unwrap();
+        assert!(window.claim("desk", true, Instant::now()).is_err());
+        assert_eq!(window.claim("laptop", true, Instant::now()), Ok(true));
+        assert!(PairWindow::from_args(&args(&["--re-pair"])).is_err());
+    }
+
+    #[test]
+    fn window_runs_out() {
+        let window = PairWindow::from_args(&args(&["--pair"])).unwrap();
+        assert!(window.claim("laptop", false, Instant::now() + PAIR_WINDOW).unwrap_err().contains("over"));
+        assert!(window.describe().is_none());
+    }
+}
@@ -0,0 +1,250 @@
+use chrome_leap_core::os_check::{Edge, EdgeHit};
+
+use crate::utils::config::Device;
+
+//one device owning [from , to) of one edge
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slot {
+    pub edge : Edge,
+    pub from : f64,
+    pub to : f64,
+    pub id : Option<String>,
+    pub ip : Option<String>,
+}
+
+impl Slot {
+    //DeviceMap key , id when configured , ip for old ip-only entries
+    pub fn key(&self) -> &str {
+        self.id.as_deref().or(self.ip.as_deref()).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self , f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f , "{} {:.2} - {:.2} -> '{}'" , self.edge.as_str() , self.from , self.to , self.key())?;
+        if let (Some(_) , Some(ip)) = (&self.id , &self.ip) {
+            write!(f , " ( hint {} )" , ip)?;
+        }
+        Ok(())
+    }
+}
+
+//which [[devices]] entry is wrong , config.rs turns the index into a line number
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayoutError {
+    pub device : usize,
+    pub reason : String,
+}
+
+impl std::fmt::Display for LayoutError {
+    fn fmt(&self , f : &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.reason)
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+macro_rules! bail {
+    ($device:expr , $($arg:tt)*) => {
+        return Err(LayoutError { device : $device , reason : format!($($arg)*) })
+    };
+}
+
+//edge segment ----> device , built from config.toml
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    slots : Vec<Slot>,
+}
+
+impl Layout {
+
+    pub fn build(devices : &[Device]) -> Result<Layout , LayoutError> {
+        let mut slots: Vec<Slot> = Vec::new();
+
+        for (i , device) in devices.iter().enumerate() {
+            let Some(name) = device.key() else {
+                bail!(i , "device on '{}' : needs an id ( its hostname ) or an ip" , device.edge);
+            };
+
+            let Some(edge) = Edge::parse(&device.edge) else {
+                let names: Vec<&str> = Edge::ALL.iter().map(|e| e.as_str()).collect();
+                bail!(i , "device {} : unknown edge '{}' , expected one of {:?}" , name , device.edge , names);
+            };
+
+            let from = device.from.unwrap_or(0.0);
+            let to = device.to.unwrap_or(1.0);
+
+            if edge.is_corner() && (device.from.is_some() || device.to.is_some()) {
+                bail!(i , "device {} : '{}' is a corner , it has no from / to" , name , device.edge);
+            }
+            if !(0.0..=1.0).contains(&from) || !(0.0..=1.0).contains(&to) || from >= to {
+                bail!(i , "device {} : segment {} - {} on '{}' must satisfy 0.0 <= from < to <= 1.0" , name , from , to , device.edge);
+            }
+
+            //two devices on the same piece of edge is ambiguous
+            if let Some(other) = slots.iter().find(|s| s.edge == edge && from < s.to && s.from < to) {
+                bail!(i , 
+                    "device {} ( {} {} - {} ) overlaps device {} ( {} - {} )",
+                    name , device.edge , from , to , other.key() , other.from , other.to
+                );
+            }
+
+            slots.push(Slot { edge , from , to , id : device.id.clone() , ip : device.ip.clone() });
+        }
+
+        Ok(Layout { slots })
+    }
+
+    pub fn resolve(&self , hit : &EdgeHit) -> Option<&str> {
+        self.slots
+            .iter()
+            .find(|s| s.edge == hit.edge && s.from <= hit.at && (hit.at < s.to || s.to >= 1.0))
+            .map(|s| s.key())
+    }
+
+    //connected peer ----> DeviceMap key , by id first , ip only for entries without an id
+    pub fn identify(&self , id : &str , ip : &str) -> Option<&str> {
+        self.slots
+            .iter()
+            .find(|s| s.id.as_deref() == Some(id))
+            .or_else(|| self.slots.iter().find(|s| s.id.is_none() && s.ip.as_deref() == Some(ip)))
+            .map(|s| s.key())
+    }
+
+    //what a reload changed , one line per added / removed segment
+    pub fn diff(&self , next : &Layout) -> Vec<String> {
+        let removed = self.slots.iter().filter(|s| !next.slots.contains(s)).map(|s| format!("- {}" , s));
+        let added = next.slots.iter().filter(|s| !self.slots.contains(s)).map(|s| format!("+ {}" , s));
+        removed.chain(added).collect()
+    }
+
+    //ip the config expects this device on , if it says one
+    pub fn ip_hint(&self , key : &str) -> Option<&str> {
+        self.slots.iter().find(|s| s.key() == key).and_then(|s| s.ip.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn device(ip : &str , edge : &str , from : Option<f64> , to : Option<f64>) -> Device {
+        Device { id : None , ip : Some(ip.to_string()) , edge : edge.to_string() , from , to }
+    }
+
+    fn named(id : &str , ip : Option<&str> , edge : &str) -> Device {
+        Device { id : Some(id.to_string()) , ip : ip.map(str::to_string) , edge : edge.to_string() , from : None , to : None }
+    }
+
+    fn hit(edge : Edge , at : f64) -> EdgeHit {
+        EdgeHit { edge , at }
+    }
+
+    #[test]
+    fn whole_edge_device() {
+        let layout = Layout::build(&[device("10.0.0.2", "left", None, None)]).unwrap();
+        assert_eq!(layout.resolve(&hit(Edge::Left, 0.0)), Some("10.0.0.2"));
+        assert_eq!(layout.resolve(&hit(Edge::Left, 1.0)), Some("10.0.0.2"));
+        assert_eq!(layout.resolve(&hit(Edge::Right, 0.5)), None);
+    }
+
+    #[test]
+    fn two_devices_share_one_edge() {
+        let layout = Layout::build(&[
+            device("10.0.0.2", "right", Some(0.0), Some(0.5)),
+            device("10.0.0.3", "right", Some(0.5), None),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.resolve(&hit(Edge::Right, 0.1)), Some("10.0.0.2"));
+        assert_eq!(layout.resolve(&hit(Edge::Right, 0.5)), Some("10.0.0.3"));
+        assert_eq!(layout.resolve(&hit(Edge::Right, 0.9)), Some("10.0.0.3"));
+    }
+
+    #[test]
+    fn top_bottom_and_corners() {
+        let layout = Layout::build(&[
+            device("10.0.0.2", "top", None, None),
+            device("10.0.0.3", "bottom_right", None, None),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.resolve(&hit(Edge::Top, 0.3)), Some("10.0.0.2"));
+        assert_eq!(layout.resolve(&hit(Edge::BottomRight, 0.0)), Some("10.0.0.3"));
+        assert_eq!(layout.resolve(&hit(Edge::Bottom, 0.3)), None);
+    }
+
+    #[test]
+    fn gap_between_segments_resolves_to_nothing() {
+        let layout = Layout::build(&[device("10.0.0.2", "left", Some(0.0), Some(0.4))]).unwrap();
+        assert_eq!(layout.resolve(&hit(Edge::Left, 0.6)), None);
+    }
+
+    #[test]
+    fn overlap_is_rejected() {
+        let err = Layout::build(&[
+            device("10.0.0.2", "right", Some(0.0), Some(0.6)),
+            device("10.0.0.3", "right", Some(0.5), None),
+        ])
+        .unwrap_err();
+        assert!(err.to_string().contains("overlaps"));
+    }
+
+    #[test]
+    fn bad_edge_and_segment_are_rejected() {
+        assert!(Layout::build(&[device("10.0.0.2", "sideways", None, None)]).is_err());
+        assert!(Layout::build(&[device("10.0.0.2", "left", Some(0.7), Some(0.2))]).is_err());
+        assert!(Layout::build(&[device("10.0.0.2", "left", None, Some(1.5))]).is_err());
+        assert!(Layout::build(&[device("10.0.0.2", "top_left", Some(0.0), None)]).is_err());
+    }
+
+    #[test]
+    fn device_is_identified_by_id_not_ip() {
+        let layout = Layout::build(&[
+            named("laptop", Some("10.0.0.2"), "left"),
+            device("10.0.0.3", "right", None, None),
+        ])
+        .unwrap();
+
+        //new dhcp lease , still the laptop
+        assert_eq!(layout.identify("laptop", "10.0.0.9"), Some("laptop"));
+        assert_eq!(layout.ip_hint("laptop"), Some("10.0.0.2"));
+        assert_eq!(layout.resolve(&hit(Edge::Left, 0.5)), Some("laptop"));
+
+        //ip-only entry still works
+        assert_eq!(layout.identify("desktop", "10.0.0.3"), Some("10.0.0.3"));
+        //a hinted ip is not enough without the id
+        assert_eq!(layout.identify("desktop", "10.0.0.2"), None);
+    }
+
+    #[test]
+    fn two_clients_on_one_host() {
+        let layout = Layout::build(&[
+            named("box-a", Some("10.0.0.2"), "left"),
+            named("box-b", Some("10.0.0.2"), "right"),
+        ])
+        .unwrap();
+
+        assert_eq!(layout.identify("box-a", "10.0.0.2"), Some("box-a"));
+        assert_eq!(layout.identify("box-b", "10.0.0.2"), Some("box-b"));
+        assert_eq!(layout.resolve(&hit(Edge::Right, 0.5)), Some("box-b"));
+    }
+
+    #[test]
+    fn device_without_id_or_ip_is_rejected() {
+        let nameless = Device { id : None , ip : None , edge : "left".to_string() , from : None , to : None };
+        assert!(Layout::build(&[nameless]).unwrap_err().to_string().contains("needs an id"));
+    }
+
+    #[test]
+    fn diff_lists_moved_devices() {
+        let old = Layout::build(&[named("laptop", None, "left"), named("desk", None, "right")]).unwrap();
+        let new = Layout::build(&[named("laptop", None, "top"), named("desk", None, "right")]).unwrap();
+
+        assert_eq!(
+            old.diff(&new),
+            vec!["- left 0.00 - 1.00 -> 'laptop'".to_string(), "+ top 0.00 - 1.00 -> 'laptop'".to_string()]
+        );
+        assert!(new.diff(&new).is_empty());
+    }
+}
@@ -1,2 +1,6 @@
-pub mod chrome;
-pub mod os_check;
\ No newline at end of file
+pub mod config;
+pub mod handshake;
+pub mod layout;
+pub mod outbox;
+pub mod quic;
+pub mod reload;
\ No newline at end of file
@@ -0,0 +1,190 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use chrome_leap_core::auth::file_stem;
+use chrome_leap_proto::GlobalMsg;
+
+use crate::utils::config::OutboxSettings;
+
+//router ---- tabs for a device that isn't connected ----> [outbox] ---- device reconnects ----> handle_peer
+//one jsonl file per device , rewritten whole on every change , it only ever holds a few throws
+//push / take run on the blocking pool , callers hold the device map lock across them
+#[derive(Debug, Clone)]
+pub struct Outbox {
+    dir : PathBuf,
+    ttl : Duration,
+    max : usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Queued {
+    //unix secs
+    queued_at : u64,
+    msg : GlobalMsg,
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+impl Outbox {
+    //None = [outbox] is off , tabs for a missing device are dropped like before
+    pub fn new(settings : &OutboxSettings) -> Option<Outbox> {
+        if !settings.enabled {
+            return None;
+        }
+        Some(Outbox {
+            dir : settings.dir.clone(),
+            ttl : Duration::from_secs(settings.ttl_secs.get()),
+            max : settings.max_per_device.max(1),
+        })
+    }
+
+    //same escaping as the key store , 'laptop/work' and 'laptop_work' get a file each
+    fn path(&self , device : &str) -> PathBuf {
+        self.dir.join(format!("{}.jsonl" , file_stem(device)))
+    }
+
+    //what is still fresh , expired and unreadable lines are logged and left out
+    fn load(&self , device : &str , now : u64) -> anyhow::Result<Vec<Queued>> {
+        let path = self.path(device);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let text = fs::read_to_string(&path).with_context(|| format!("read {}" , path.display()))?;
+        let mut queued = Vec::new();
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            match serde_json::from_str::<Queued>(line) {
+                Ok(q) if now.saturating_sub(q.queued_at) < self.ttl.as_secs() => queued.push(q),
+                Ok(q) => println!("[OUTBOX] tabs {} for '{}' expired , dropping" , q.msg.id().unwrap_or_default() , device),
+                Err(e) => println!("[OUTBOX] bad line in {} , dropping : {}" , path.display() , e),
+            }
+        }
+        Ok(queued)
+    }
+
+    fn store(&self , device : &str , queued : &[Queued]) -> anyhow::Result<()> {
+        let path = self.path(device);
+        if queued.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path).with_context(|| format!("remove {}" , path.display()))?;
+            }
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir).with_context(|| format!("create {}" , self.dir.display()))?;
+        let mut text = String::new();
+        for q in queued {
+            text += &serde_json::to_string(q)?;
+            text.push('\n');
+        }
+
+        //write next to it and rename so a crash never leaves half a file
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp , text).with_context(|| format!("write {}" , tmp.display()))?;
+        fs::rename(&tmp , &path).with_context(|| format!("write {}" , path.display()))?;
+        Ok(())
+    }
+
+    //hold `msg` until `device` is back , the oldest goes when it's full , returns how many are waiting
+    pub async fn push(&self , device : &str , msg : GlobalMsg) -> anyhow::Result<usize> {
+        let (outbox , device) = (self.clone() , device.to_string());
+        tokio::task::spawn_blocking(move || outbox.push_at(&device , msg , now_secs())).await?
+    }
+
+    fn push_at(&self , device : &str , msg : GlobalMsg , now : u64) -> anyhow::Result<usize> {
+        let mut queued = self.load(device , now)?;
+        queued.push(Queued { queued_at : now , msg });
+        while queued.len() > self.max {
+            let dropped = queued.remove(0);
+            println!("[OUTBOX] '{}' has {} waiting , dropping the oldest ( tabs {} )" , device , self.max , dropped.msg.id().unwrap_or_default());
+        }
+        self.store(device , &queued)?;
+        Ok(queued.len())
+    }
+
+    //everything still fresh for `device` , oldest first , the file is gone afterwards
+    pub async fn take(&self , device : &str) -> anyhow::Result<Vec<GlobalMsg>> {
+        let (outbox , device) = (self.clone() , device.to_string());
+        tokio::task::spawn_blocking(move || outbox.take_at(&device , now_secs())).await?
+    }
+
+    fn take_at(&self , device : &str , now : u64) -> anyhow::Result<Vec<GlobalMsg>> {
+        let queued = self.load(device , now)?;
+        self.store(device , &[])?;
+        Ok(queued.into_iter().map(|q| q.msg).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZeroU64;
+    use chrome_leap_proto::TabInfo;
+
+    fn outbox(name : &str , ttl_secs : u64 , max : usize) -> Outbox {
+        let dir = std::env::temp_dir().join(format!("chrome_leap-outbox-{}-{}" , name , std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        Outbox::new(&OutboxSettings { enabled : true , dir , ttl_secs : NonZeroU64::new(ttl_secs).unwrap() , max_per_device : max }).unwrap()
+    }
+
+    fn tabs(id : u64) -> GlobalMsg {
+        GlobalMsg::Tabs { id , tabs : vec![TabInfo::from_url("https://a.example")] , time : "1".to_string() , window : None }
+    }
+
+    #[test]
+    fn disabled_is_none() {
+        assert!(Outbox::new(&OutboxSettings::default()).is_none());
+    }
+
+    #[tokio::test]
+    async fn holds_until_taken() {
+        let outbox = outbox("hold" , 60 , 10);
+        assert_eq!(outbox.push("laptop/work" , tabs(1)).await.unwrap(), 1);
+        assert_eq!(outbox.push("laptop/work" , tabs(2)).await.unwrap(), 2);
+        assert!(outbox.take("desktop").await.unwrap().is_empty());
+
+        assert!(outbox.path("laptop/work").exists());
+        assert_eq!(outbox.take("laptop/work").await.unwrap(), vec![tabs(1) , tabs(2)]);
+        assert!(outbox.take("laptop/work").await.unwrap().is_empty());
+        assert!(!outbox.path("laptop/work").exists());
+        let _ = fs::remove_dir_all(&outbox.dir);
+    }
+
+    #[test]
+    fn ids_never_share_a_file() {
+        let outbox = outbox("names" , 60 , 10);
+        assert_ne!(outbox.path("laptop/work"), outbox.path("laptop_work"));
+        assert_eq!(outbox.path("../etc").parent(), Some(outbox.dir.as_path()));
+
+        outbox.push_at("laptop/work" , tabs(1) , 1_000).unwrap();
+        outbox.push_at("laptop_work" , tabs(2) , 1_000).unwrap();
+        assert_eq!(outbox.take_at("laptop/work" , 1_000).unwrap(), vec![tabs(1)]);
+        assert_eq!(outbox.take_at("laptop_work" , 1_000).unwrap(), vec![tabs(2)]);
+        let _ = fs::remove_dir_all(&outbox.dir);
+    }
+
+    #[test]
+    fn oldest_goes_when_full() {
+        let outbox = outbox("full" , 60 , 2);
+        for i in 1..=3 {
+            outbox.push_at("laptop" , tabs(i) , 1_000).unwrap();
+        }
+        assert_eq!(outbox.take_at("laptop" , 1_000).unwrap(), vec![tabs(2) , tabs(3)]);
+        let _ = fs::remove_dir_all(&outbox.dir);
+    }
+
+    #[test]
+    fn expired_are_dropped() {
+        let outbox = outbox("ttl" , 60 , 10);
+        outbox.push_at("laptop" , tabs(1) , 1_000).unwrap();
+        outbox.push_at("laptop" , tabs(2) , 1_050).unwrap();
+        assert_eq!(outbox.take_at("laptop" , 1_070).unwrap(), vec![tabs(2)]);
+        let _ = fs::remove_dir_all(&outbox.dir);
+    }
+}
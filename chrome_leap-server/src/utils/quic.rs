@@ -0,0 +1,89 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use quinn::crypto::rustls::QuicServerConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+
+use chrome_leap_core::transport::{self, ALPN, SERVER_NAME};
+
+//next to config.toml , generated on first quic start and reused after so the client pin stays valid
+pub const CERT_DIR: &str = "chrome_leap-cert";
+
+pub fn load_or_create_cert(dir : &Path) -> anyhow::Result<(CertificateDer<'static>, PrivateKeyDer<'static>)> {
+    let cert_path = dir.join("cert.der");
+    let key_path = dir.join("key.der");
+
+    if cert_path.exists() && key_path.exists() {
+        let cert = fs::read(&cert_path).with_context(|| format!("read {}", cert_path.display()))?;
+        let key = fs::read(&key_path).with_context(|| format!("read {}", key_path.display()))?;
+        return Ok((CertificateDer::from(cert), PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key))));
+    }
+
+    println!("[QUIC] no certificate in {} , generating a self-signed one", dir.display());
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+    let cert_der = cert.serialize_der()?;
+    let key_der = cert.serialize_private_key_der();
+
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    fs::write(&cert_path, &cert_der).with_context(|| format!("write {}", cert_path.display()))?;
+    fs::write(&key_path, &key_der).with_context(|| format!("write {}", key_path.display()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&key_path, fs::Permissions::from_mode(0o600))?;
+    }
+
+    Ok((CertificateDer::from(cert_der), PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_der))))
+}
+
+//returns the endpoint and the fingerprint clients have to pin
+pub fn endpoint(addr : SocketAddr , cert_dir : &Path) -> anyhow::Result<(quinn::Endpoint, String)> {
+    let (cert , key) = load_or_create_cert(cert_dir)?;
+    let fingerprint = transport::fingerprint(&cert);
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)?;
+    tls.alpn_protocols = vec![ALPN.to_vec()];
+
+    let mut server_config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+    server_config.transport_config(transport::quic_transport_config());
+
+    let endpoint = quinn::Endpoint::server(server_config, addr)?;
+    Ok((endpoint , fingerprint))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cert_is_persisted_and_reused() {
+        let dir = std::env::temp_dir().join(format!("chrome_leap-cert-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let (first , _) = load_or_create_cert(&dir).unwrap();
+        let (second , _) = load_or_create_cert(&dir).unwrap();
+        assert_eq!(transport::fingerprint(&first), transport::fingerprint(&second));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn endpoint_starts_with_generated_cert() {
+        let dir = std::env::temp_dir().join(format!("chrome_leap-endpoint-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let (endpoint , fingerprint) = endpoint("127.0.0.1:0".parse().unwrap(), &dir).unwrap();
+        assert_eq!(fingerprint.len(), 64);
+        assert!(endpoint.local_addr().unwrap().port() != 0);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
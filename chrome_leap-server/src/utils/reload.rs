@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::time::sleep;
+
+use crate::utils::config::{validate, Config};
+use crate::utils::layout::Layout;
+
+//how often config.toml is looked at , editing by hand doesn't need faster
+const POLL: Duration = Duration::from_secs(1);
+
+//config.toml ---- [poll] ----> validated Config + Layout
+//a broken edit is logged and skipped , whoever called us keeps the old one
+pub struct ConfigWatch {
+    path : PathBuf,
+    content : String,
+}
+
+impl ConfigWatch {
+    //`content` is what the server started with , so the first poll doesn't count as a change
+    pub fn new(path : impl Into<PathBuf> , content : String) -> ConfigWatch {
+        ConfigWatch { path : path.into() , content }
+    }
+
+    pub async fn next(&mut self) -> (Config , Layout) {
+        loop {
+            sleep(POLL).await;
+            if let Some(next) = self.check() {
+                return next;
+            }
+        }
+    }
+
+    //one look at the file , Some = valid and different from the last content seen
+    //compares what's in it , not the mtime , which is coarse on some filesystems and changes on a plain touch
+    fn check(&mut self) -> Option<(Config , Layout)> {
+        //editors write the file in several steps , a read in between can fail
+        let content = std::fs::read_to_string(&self.path).ok()?;
+        if content == self.content {
+            return None;
+        }
+        self.content = content;
+
+        match validate(&self.content) {
+            Ok(next) => Some(next),
+            Err(e) => {
+                println!("[CONFIG] invalid edit , keeping the old config : {}:{}" , self.path.display() , e);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_up_a_valid_edit_and_skips_a_broken_one() {
+        let path = std::env::temp_dir().join(format!("chrome_leap-reload-{}.toml", std::process::id()));
+        let first = "[[devices]]\nid = \"laptop\"\nedge = \"left\"\n".to_string();
+        std::fs::write(&path, &first).unwrap();
+
+        let mut watch = ConfigWatch::new(&path, first);
+        assert!(watch.check().is_none(), "the starting config is not a change");
+
+        std::fs::write(&path, "[[devices]]\nid = \"laptop\"\nedge = \"nowhere\"\n").unwrap();
+        assert!(watch.check().is_none(), "a broken config must not come out");
+        //still broken , no second attempt
+        assert!(watch.check().is_none());
+
+        std::fs::write(&path, "[[devices]]\nid = \"laptop\"\nedge = \"right\"\n").unwrap();
+        let (config , _layout) = watch.check().unwrap();
+        assert_eq!(config.devices()[0].edge, "right");
+
+        //same content written again ( a save without edits ) is not a reload
+        std::fs::write(&path, "[[devices]]\nid = \"laptop\"\nedge = \"right\"\n").unwrap();
+        assert!(watch.check().is_none());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
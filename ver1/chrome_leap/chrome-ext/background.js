@@ -5,10 +5,13 @@ let tabsGlobal = null
 let reconnTimeout = 1000;
 async function conn() {
     try {
-        ws = new WebSocket(ws_url);
+        // [listen] token / WS_TOKEN on the forwarder , set with chrome.storage.local.set({ token : "..." })
+        const { token } = await chrome.storage.local.get("token");
+        ws = new WebSocket(token ? ws_url + "/?token=" + encodeURIComponent(token) : ws_url);
 
         ws.onopen = ()=> {
             console.log(" [ws] - connected");
+            register().catch((err) => console.error("[register] - error : " , err));
         };
 
         ws.onclose = ()=> {
@@ -25,21 +28,16 @@ async function conn() {
 
                     console.log(tabsGlobal);
                     console.log(data)
-                    ws.send(JSON.stringify({action : "tabs" , tabs : tabsGlobal || [] , edge : data.edge || ""}));
+                    sendTabs(data).catch((err) => console.error("[get_tabs] - error : " , err));
                     
                 } else if (data.action === "tel") {
-                    const windowId = null
-                    chrome.windows.create({
-                        focused: true
-                    } , (win)=> windowId = win.id);
-
-                    for (const url of data.urls) {
-                        chrome.tabs.create({
-                            windowId: windowId,
-                            url: url,
-                            active: true
+                    // the other side only acks once we say they're open
+                    openTabs(data.tabs || [] , data.window)
+                        .then(() => ws.send(JSON.stringify({ action : "opened" , id : data.id || 0 , failed : [] })))
+                        .catch((err) => {
+                            console.error("[tel] - error : " , err);
+                            ws.send(JSON.stringify({ action : "opened" , id : data.id || 0 , error : String(err) }));
                         });
-                    }
                 }
             } catch (err) {
                 console.error("[msg ws] - error : " , err);
@@ -58,15 +56,145 @@ async function conn() {
 conn()
 
 
+let windowGlobal = null
+
+// random per profile , kept in storage so the forwarder sees the same id after a restart
+async function profileId() {
+    const { profile } = await chrome.storage.local.get("profile");
+    if (profile) {
+        return profile;
+    }
+    const id = crypto.randomUUID();
+    await chrome.storage.local.set({ profile : id });
+    return id;
+}
+
+function browserName() {
+    const brands = (navigator.userAgentData && navigator.userAgentData.brands) || [];
+    const brand = brands.find((b) => !/chromium|not/i.test(b.brand));
+    return brand ? brand.brand : "chrome";
+}
+
+// who we are and which windows are ours ( chrome_leap_proto::ClientMsg::Register ) , sent again whenever focus moves
+async function register() {
+    if (!ws || ws.readyState !== WebSocket.OPEN) {
+        return;
+    }
+    const wins = await chrome.windows.getAll({ populate : true });
+    const windows = wins.map((w) => {
+        const active = (w.tabs || []).find((t) => t.active);
+        return { id : w.id , title : active ? active.title || "" : "" , focused : !!w.focused };
+    });
+    ws.send(JSON.stringify({ action : "register" , browser : browserName() , profile : await profileId() , windows : windows }));
+}
+
+// every profile gets the get_tabs , the forwarder keeps the answer from the focused window ( chrome_leap_proto::ClientMsg::Tabs )
+async function sendTabs(data) {
+    let focused = false;
+    try {
+        const win = await chrome.windows.getLastFocused();
+        focused = !!win.focused;
+    } catch (err) {
+        console.warn("[focus] - " , err);
+    }
+    ws.send(JSON.stringify({action : "tabs" , id : data.id || 0 , focused : focused , tabs : tabsGlobal || [] , edge : data.edge || "" , window : windowGlobal}));
+}
+
+// one TabInfo per tab , same field names as chrome_leap_proto::TabInfo
+async function tabInfo(tab) {
+    let group = null;
+    if (tab.groupId !== undefined && tab.groupId !== chrome.tabGroups.TAB_GROUP_ID_NONE) {
+        try {
+            const g = await chrome.tabGroups.get(tab.groupId);
+            group = { id : g.id , name : g.title || "" , color : g.color };
+        } catch (err) {
+            console.warn("[group] - " , err);
+        }
+    }
+
+    return {
+        url : tab.url,
+        title : tab.title,
+        pinned : tab.pinned,
+        group : group,
+        active : tab.active,
+        index : tab.index,
+        incognito : tab.incognito,
+        fav_icon_url : tab.favIconUrl,
+    };
+}
+
+// tabs another computer threw at us ( chrome_leap_proto::ServerMsg::Tel ) , one new window each for normal and incognito
+async function openTabs(tabs , bounds) {
+    const ordered = [...tabs].sort((a , b) => (a.index || 0) - (b.index || 0));
+    for (const incognito of [false , true]) {
+        const group = ordered.filter((t) => !!t.incognito === incognito);
+        if (group.length > 0) {
+            await openWindow(group , incognito , bounds);
+        }
+    }
+}
+
+async function openWindow(tabs , incognito , bounds) {
+    const options = { focused : true , incognito : incognito , url : tabs.map((t) => t.url) };
+    // minimized / maximized / fullscreen can't be mixed with a position
+    if (bounds && bounds.state && bounds.state !== "normal") {
+        options.state = bounds.state;
+    } else if (bounds) {
+        for (const key of ["left" , "top" , "width" , "height"]) {
+            if (bounds[key] !== undefined && bounds[key] !== null) {
+                options[key] = bounds[key];
+            }
+        }
+    }
+
+    const win = await chrome.windows.create(options);
+    const created = await chrome.tabs.query({ windowId : win.id });
+    created.sort((a , b) => a.index - b.index);
+
+    // same index = same tab , windows.create keeps the url order
+    const groups = new Map();
+    for (let i = 0; i < tabs.length && i < created.length; i++) {
+        const tab = tabs[i];
+        const id = created[i].id;
+        if (tab.pinned) {
+            await chrome.tabs.update(id , { pinned : true });
+        }
+        if (tab.group) {
+            const key = tab.group.id;
+            if (!groups.has(key)) {
+                groups.set(key , { info : tab.group , ids : [] });
+            }
+            groups.get(key).ids.push(id);
+        }
+        if (tab.active) {
+            await chrome.tabs.update(id , { active : true });
+        }
+    }
+
+    for (const { info , ids } of groups.values()) {
+        try {
+            const groupId = await chrome.tabs.group({ tabIds : ids , createProperties : { windowId : win.id } });
+            await chrome.tabGroups.update(groupId , { title : info.name , color : info.color });
+        } catch (err) {
+            console.warn("[group] - " , err);
+        }
+    }
+}
+
 async function update() {
     try {
         const [tab] = await chrome.tabs.query({active : true , currentWindow : true});
         if (tab) {
             let tabs = await chrome.tabs.query({ windowId: tab.windowId })
             if (tabs) {
-                tabsGlobal = tabs.map(val => val.url);
+                tabsGlobal = await Promise.all(tabs.map(tabInfo));
             }
+
+            const win = await chrome.windows.get(tab.windowId);
+            windowGlobal = { left : win.left , top : win.top , width : win.width , height : win.height , state : win.state };
         }
+        await register();
     } catch (err) {
         console.error("update" , err);
     }